@@ -0,0 +1,185 @@
+use awc::{
+    http::{header, Method, StatusCode},
+    Client,
+};
+use bytes::Bytes;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{
+    io::{self, Read, Write},
+    net::SocketAddr,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+use crate::trust::room::{RetentionPolicy, RoomRetention};
+
+/// Characters escaped in a path segment or query value: everything but
+/// the unreserved ones, so that e.g. `/`, `?` and `#` in a room name
+/// aren't taken for URL syntax.
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Time a request to the admin API may take, response body included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest response body accepted, in bytes.
+const RESPONSE_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Admin commands run against the admin API of a running server.
+#[derive(StructOpt, Debug)]
+pub enum AdminCommand {
+    /// Write the history of a room to stdout.
+    Export {
+        room: String,
+
+        /// Export format: `jsonl` (importable) or `transcript`.
+        #[structopt(long, default_value = "jsonl")]
+        format: String,
+    },
+
+    /// Import a JSON Lines history export into a room without history.
+    Import {
+        room: String,
+
+        /// File holding the export; read from stdin when omitted.
+        input: Option<std::path::PathBuf>,
+    },
+
+    /// Drop a room without members and not on legal hold, with its history
+    /// and shared files.
+    DeleteRoom { room: String },
+
+    /// Show the retention settings of a room, or replace them with `--set`.
+    Retention {
+        room: String,
+
+        /// Replace the settings with the ones given, instead of showing them.
+        #[structopt(long)]
+        set: bool,
+
+        /// Age in seconds after which messages are pruned.
+        #[structopt(long, requires = "set")]
+        max_age_secs: Option<u64>,
+
+        /// Number of most recent messages kept.
+        #[structopt(long, requires = "set")]
+        max_count: Option<usize>,
+
+        /// Suspend pruning the room's history.
+        #[structopt(long, requires = "set")]
+        legal_hold: bool,
+    },
+}
+
+impl AdminCommand {
+    /// Run the command against the admin API on the address specified.
+    pub async fn run(self, addr: SocketAddr, token: &str) -> io::Result<()> {
+        let room_path = |room: &str| format!("/admin/rooms/{}", encode(room));
+        let (method, path, body) = match self {
+            AdminCommand::Export { room, format } => (
+                Method::GET,
+                format!("{}/history?format={}", room_path(&room), encode(&format)),
+                Vec::new(),
+            ),
+
+            AdminCommand::Import { room, input } => {
+                let body = match input {
+                    Some(path) => std::fs::read(path)?,
+                    None => {
+                        let mut body = Vec::new();
+                        io::stdin().read_to_end(&mut body)?;
+                        body
+                    }
+                };
+
+                (Method::POST, format!("{}/history", room_path(&room)), body)
+            }
+
+            AdminCommand::DeleteRoom { room } => (Method::DELETE, room_path(&room), Vec::new()),
+
+            AdminCommand::Retention {
+                room, set: false, ..
+            } => (
+                Method::GET,
+                format!("{}/retention", room_path(&room)),
+                Vec::new(),
+            ),
+
+            AdminCommand::Retention {
+                room,
+                set: true,
+                max_age_secs,
+                max_count,
+                legal_hold,
+            } => {
+                let retention = RoomRetention {
+                    policy: RetentionPolicy {
+                        max_age_secs,
+                        max_count,
+                    },
+                    legal_hold,
+                };
+
+                let mut body = serde_json::to_vec(&retention)?;
+                body.push(b'\n');
+                (Method::PUT, format!("{}/retention", room_path(&room)), body)
+            }
+        };
+
+        let (status, response) = request(addr, token, method.clone(), &path, body).await?;
+        if !status.is_success() {
+            let message = format!(
+                "{} {} failed with status {}: {}",
+                method,
+                path,
+                status,
+                String::from_utf8_lossy(&response).trim()
+            );
+            return Err(io::Error::other(message));
+        }
+
+        let mut stdout = io::stdout();
+        stdout.write_all(&response)?;
+        if !response.is_empty() && !response.ends_with(b"\n") {
+            stdout.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Percent-encode a path segment or query value.
+fn encode(component: &str) -> String {
+    utf8_percent_encode(component, COMPONENT).to_string()
+}
+
+/// Send a request to the admin API, returning the response status and body.
+async fn request(
+    addr: SocketAddr,
+    token: &str,
+    method: Method,
+    path: &str,
+    body: Vec<u8>,
+) -> io::Result<(StatusCode, Bytes)> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).finish();
+    let response = client
+        .request(method, format!("http://{}{}", addr, path))
+        .bearer_auth(token)
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .send_body(body)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    let status = response.status();
+    let body = response
+        .timeout(REQUEST_TIMEOUT)
+        .body()
+        .limit(RESPONSE_LIMIT)
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    Ok((status, body))
+}
@@ -1,27 +1,95 @@
+use crate::trust::admin::{
+    delete_room, export_history, get_retention, import_history, set_retention, AdminConfig,
+};
+use crate::trust::attachments::{download_attachment, upload_attachment, AttachmentConfig};
 use crate::trust::codec::TrustTcpCodec;
+use crate::trust::hooks::post_hook_message;
+use crate::trust::metrics::{self, metrics_handler};
 use crate::trust::server::TrustServer;
-use crate::trust::user::User;
+use crate::trust::user::{SessionConfig, User};
 use actix::io::FramedWrite;
 use actix::Actor;
 use actix::Addr;
 use actix::StreamHandler;
+use actix_web::dev::Server;
+use actix_web::{web, App, HttpServer};
 use std::net::SocketAddr;
 use tokio::io::split;
 use tokio::net::TcpListener;
 use tokio_util::codec::FramedRead;
 
-/// Setup TCP listener for Trust Chat Server on a socket address specified.
-pub async fn start_tcp_listener(addr: SocketAddr, server: Addr<TrustServer>) {
-    let server = server.clone();
-    let listener = TcpListener::bind(addr).await.unwrap();
+/// Bind a TCP listener for Trust Chat Server on a socket address specified.
+pub async fn bind_tcp_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    TcpListener::bind(addr).await
+}
 
-    while let Ok((stream, _)) = listener.accept().await {
+/// Accept Trust Chat Server sessions on a bound TCP listener.
+///
+/// Every session accepted by the listener is configured with `config`.
+pub async fn start_tcp_listener(
+    listener: TcpListener,
+    server: Addr<TrustServer>,
+    config: SessionConfig,
+) {
+    while let Ok((stream, peer_addr)) = listener.accept().await {
         let server = server.clone();
+        let config = config.clone();
 
         User::create(|ctx| {
             let (r, w) = split(stream);
             User::add_stream(FramedRead::new(r, TrustTcpCodec), ctx);
-            User::new(server, FramedWrite::new(w, TrustTcpCodec, ctx))
+            User::new(
+                server,
+                FramedWrite::new(w, TrustTcpCodec, ctx),
+                peer_addr,
+                config,
+            )
         });
     }
 }
+
+/// Setup HTTP server exposing the Prometheus metrics, attachment
+/// uploads/downloads and the admin API on the socket address specified.
+pub fn start_http_server(
+    addr: SocketAddr,
+    chat_server: Addr<TrustServer>,
+    attachments: AttachmentConfig,
+    admin: AdminConfig,
+) -> std::io::Result<Server> {
+    metrics::init();
+    std::fs::create_dir_all(&attachments.dir)?;
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(chat_server.clone()))
+            .app_data(web::Data::new(attachments.clone()))
+            .app_data(web::Data::new(admin.clone()))
+            .app_data(web::PayloadConfig::new(attachments.max_size))
+            .route("/metrics", web::get().to(metrics_handler))
+            .route(
+                "/rooms/{room}/attachments",
+                web::post().to(upload_attachment),
+            )
+            .route("/attachments/{id}", web::get().to(download_attachment))
+            .route("/hooks/{token}", web::post().to(post_hook_message))
+            .route("/admin/rooms/{room}", web::delete().to(delete_room))
+            .route("/admin/rooms/{room}/history", web::get().to(export_history))
+            .route(
+                "/admin/rooms/{room}/history",
+                web::post().to(import_history),
+            )
+            .route(
+                "/admin/rooms/{room}/retention",
+                web::get().to(get_retention),
+            )
+            .route(
+                "/admin/rooms/{room}/retention",
+                web::put().to(set_retention),
+            )
+    })
+    .workers(1)
+    .bind(addr)?
+    .run();
+
+    Ok(server)
+}
@@ -0,0 +1,60 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, IsTerminal},
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Output format of the server logs.
+#[derive(Debug, Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unsupported log format [{}]", format)),
+        }
+    }
+}
+
+/// Install the global logger.
+///
+/// Logs are written to stderr unless a file is specified; colors are only
+/// used when stderr is an interactive terminal.
+pub fn init_logger(filter: &str, format: LogFormat, file: Option<&Path>) -> io::Result<()> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+    let builder = fmt().with_env_filter(filter);
+
+    let result = match (format, file) {
+        (LogFormat::Text, None) => builder
+            .with_writer(io::stderr)
+            .with_ansi(io::stderr().is_terminal())
+            .try_init(),
+        (LogFormat::Json, None) => builder.json().with_writer(io::stderr).try_init(),
+        (LogFormat::Text, Some(path)) => builder
+            .with_writer(Mutex::new(open_log_file(path)?))
+            .with_ansi(false)
+            .try_init(),
+        (LogFormat::Json, Some(path)) => builder
+            .json()
+            .with_writer(Mutex::new(open_log_file(path)?))
+            .try_init(),
+    };
+
+    result.map_err(|err| io::Error::other(err.to_string()))
+}
+
+/// Open a log file for appending, creating it if it does not exist.
+fn open_log_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -1,8 +1,23 @@
 use actix::Actor;
-use loaders::start_tcp_listener;
+use admin_client::AdminCommand;
+use loaders::{bind_tcp_listener, start_http_server, start_tcp_listener};
+use logging::{init_logger, LogFormat};
+use std::time::Duration;
+use std::{collections::HashMap, fs, path::PathBuf};
 use structopt::StructOpt;
-use trust::server::TrustServer;
+use tracing::info;
+use trust::{
+    admin::AdminConfig,
+    attachments::AttachmentConfig,
+    plugins::{RoomWebhook, WebhookConfig, WebhookPlugin, WelcomePlugin, WordFilterPlugin},
+    pruner::Pruner,
+    room::RetentionPolicy,
+    server::{IncomingHook, ServerConfig, TrustServer},
+    user::{Protocol, SessionConfig},
+};
+mod admin_client;
 mod loaders;
+mod logging;
 mod trust;
 
 /// CLI Args
@@ -11,15 +26,286 @@ mod trust;
 struct CliArgs {
     #[structopt(short, long, default_value = "1234")]
     port: u16,
+
+    /// Port of an additional listener sending JSON frames instead of plain text.
+    #[structopt(long)]
+    json_port: Option<u16>,
+
+    /// Prefix plain text chat messages with their sequence number, id and timestamp.
+    #[structopt(long)]
+    message_prefix: bool,
+
+    /// Only recognise commands prefixed with `/`, sending any other line as a message.
+    #[structopt(long)]
+    slash_commands: bool,
+
+    /// Port of the HTTP endpoint serving Prometheus metrics.
+    #[structopt(long, default_value = "9100")]
+    http_port: u16,
+
+    /// Bearer token enabling the admin API; admin commands send it to the server.
+    #[structopt(long)]
+    admin_token: Option<String>,
+
+    /// Directory files shared in rooms are stored in.
+    #[structopt(long, default_value = "attachments", parse(from_os_str))]
+    attachments_dir: PathBuf,
+
+    /// Largest file that can be shared in a room, in bytes.
+    #[structopt(long, default_value = "10485760")]
+    max_attachment_bytes: usize,
+
+    /// Comma separated content types of files that can be shared in a room.
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "image/png,image/jpeg,image/gif,image/webp,text/plain,application/pdf"
+    )]
+    attachment_types: Vec<String>,
+
+    /// Seconds after which messages are pruned from rooms without their own retention policy.
+    #[structopt(long)]
+    retention_max_age_secs: Option<u64>,
+
+    /// Number of messages kept in rooms without their own retention policy.
+    #[structopt(long)]
+    retention_max_count: Option<usize>,
+
+    /// Seconds between two prunes of the room histories.
+    #[structopt(long, default_value = "60")]
+    prune_interval_secs: u64,
+
+    /// Greeting the welcome plugin sends to a room when a user joins it;
+    /// `{username}` and `{room}` are replaced.
+    #[structopt(long)]
+    welcome_message: Option<String>,
+
+    /// Comma separated words the word filter plugin masks in messages.
+    #[structopt(long, use_delimiter = true)]
+    blocked_words: Vec<String>,
+
+    /// Post the events of a room to a URL, given as `{room}={url}`; can be repeated.
+    #[structopt(long, requires = "webhook-secret", number_of_values = 1)]
+    webhook: Vec<RoomWebhook>,
+
+    /// Key webhook requests are signed with (HMAC-SHA256).
+    #[structopt(long)]
+    webhook_secret: Option<String>,
+
+    /// Number of times a webhook delivery is attempted before it is dead-lettered.
+    #[structopt(long, default_value = "5")]
+    webhook_max_attempts: u32,
+
+    /// Milliseconds waited before retrying a failed webhook delivery the first
+    /// time; doubled after every attempt.
+    #[structopt(long, default_value = "1000")]
+    webhook_backoff_ms: u64,
+
+    /// File webhook deliveries which kept failing are recorded in.
+    #[structopt(long, default_value = "webhooks-dead-letter.jsonl", parse(from_os_str))]
+    webhook_dead_letter_file: PathBuf,
+
+    /// Let CI systems and other tools post into a room with `POST /hooks/{token}`,
+    /// given as `{token}={room}={display name}`; can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    incoming_hook: Vec<IncomingHook>,
+
+    /// Number of messages each incoming webhook may post per minute.
+    #[structopt(long, default_value = "30")]
+    hook_rate_limit: u32,
+
+    /// Number of messages kept for a session whose resume grace window
+    /// elapsed, until its client resumes it.
+    #[structopt(long, default_value = "100")]
+    offline_queue_capacity: usize,
+
+    /// Number of sessions whose resume grace window elapsed messages are kept for.
+    #[structopt(long, default_value = "1000")]
+    offline_queue_max_sessions: usize,
+
+    /// Seconds messages are kept for a session whose resume grace window elapsed.
+    #[structopt(long, default_value = "604800")]
+    offline_queue_ttl_secs: u64,
+
+    /// Seconds a dropped session can be resumed with its token before it is evicted.
+    #[structopt(long, default_value = "60")]
+    resume_grace_secs: u64,
+
+    /// Seconds a typing indicator lasts unless the client refreshes it.
+    #[structopt(long, default_value = "5")]
+    typing_timeout_secs: u64,
+
+    /// Broadcast read receipts to room members when a user marks messages read.
+    #[structopt(long)]
+    read_receipts: bool,
+
+    /// Password users can send with `OPER` to become server operators.
+    #[structopt(long)]
+    operator_password: Option<String>,
+
+    /// File holding the message of the day sent to clients when they connect.
+    #[structopt(long, parse(from_os_str))]
+    motd_file: Option<PathBuf>,
+
+    /// Seconds between heartbeat pings sent to each client.
+    #[structopt(long, default_value = "60")]
+    heartbeat_interval_secs: u64,
+
+    /// Seconds without hearing from a client before it is disconnected.
+    #[structopt(long, default_value = "300")]
+    client_timeout_secs: u64,
+
+    /// Seconds without activity other than heartbeats before a user is shown as idle.
+    #[structopt(long, default_value = "600")]
+    idle_timeout_secs: u64,
+
+    /// Seconds between heartbeat pings sent to clients of the JSON listener;
+    /// `--heartbeat-interval-secs` when not given.
+    #[structopt(long)]
+    json_heartbeat_interval_secs: Option<u64>,
+
+    /// Seconds without hearing from a client of the JSON listener before it is
+    /// disconnected; `--client-timeout-secs` when not given.
+    #[structopt(long)]
+    json_client_timeout_secs: Option<u64>,
+
+    /// Log filter directives (e.g. `info,trust::trust::user=debug`).
+    #[structopt(long, default_value = "info")]
+    log_filter: String,
+
+    /// Log output format: `text` or `json`.
+    #[structopt(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Write logs to this file instead of stderr.
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<AdminCommand>,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args = CliArgs::from_args();
-    let server = TrustServer::default().start();
+    let http_address = format!("127.0.0.1:{}", args.http_port).parse().unwrap();
+    if let Some(command) = args.command {
+        let token = args.admin_token.unwrap_or_default();
+        if let Err(err) = command.run(http_address, &token).await {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    init_logger(&args.log_filter, args.log_format, args.log_file.as_deref())?;
+
+    let config = ServerConfig {
+        resume_grace: Duration::from_secs(args.resume_grace_secs),
+        typing_timeout: Duration::from_secs(args.typing_timeout_secs),
+        read_receipts: args.read_receipts,
+        operator_password: args.operator_password,
+        motd: args
+            .motd_file
+            .map(fs::read_to_string)
+            .transpose()?
+            .map(|motd| motd.trim_end().to_string()),
+        retention: RetentionPolicy {
+            max_age_secs: args.retention_max_age_secs,
+            max_count: args.retention_max_count,
+        },
+        incoming_hooks: args.incoming_hook,
+        hook_rate_limit: args.hook_rate_limit,
+        offline_queue_capacity: args.offline_queue_capacity,
+        offline_queue_max_sessions: args.offline_queue_max_sessions,
+        offline_queue_ttl: Duration::from_secs(args.offline_queue_ttl_secs),
+        attachments_dir: args.attachments_dir.clone(),
+    };
+    let mut server = TrustServer::new(config);
+    if let Some(greeting) = &args.welcome_message {
+        server = server.with_plugin(WelcomePlugin::new(greeting));
+    }
+    if !args.blocked_words.is_empty() {
+        server = server.with_plugin(WordFilterPlugin::new(&args.blocked_words));
+    }
+    if !args.webhook.is_empty() {
+        let mut urls = HashMap::<_, Vec<_>>::new();
+        for webhook in args.webhook {
+            urls.entry(webhook.room).or_default().push(webhook.url);
+        }
+
+        server = server.with_plugin(WebhookPlugin::new(WebhookConfig {
+            urls,
+            secret: args.webhook_secret.unwrap_or_default(),
+            max_attempts: args.webhook_max_attempts,
+            initial_backoff: Duration::from_millis(args.webhook_backoff_ms),
+            dead_letter_file: args.webhook_dead_letter_file,
+        }));
+    }
+    let server = server.start();
     let address = format!("127.0.0.1:{}", args.port).parse().unwrap();
-    log!("Starting application on {:?}", address);
+    info!(%address, "Starting application");
+    info!(%http_address, "Serving metrics on /metrics");
+
+    // Bind the chat listeners before starting any actor, so a port already
+    // in use ends the process with its error.
+    let listener = bind_tcp_listener(address).await?;
+    let json_listener = match args.json_port {
+        Some(json_port) => {
+            let json_address = format!("127.0.0.1:{}", json_port).parse().unwrap();
+            info!(%json_address, "Starting JSON listener");
+            Some(bind_tcp_listener(json_address).await?)
+        }
+        None => None,
+    };
+
+    Pruner::new(
+        server.clone(),
+        args.attachments_dir.clone(),
+        Duration::from_secs(args.prune_interval_secs),
+    )
+    .start();
+
+    let attachments = AttachmentConfig {
+        dir: args.attachments_dir,
+        max_size: args.max_attachment_bytes,
+        allowed_types: args.attachment_types,
+        base_url: format!("http://{}", http_address),
+    };
+    let admin = AdminConfig {
+        token: args.admin_token,
+    };
+    let http_server = start_http_server(http_address, server.clone(), attachments, admin)?;
+    let session_config = SessionConfig {
+        heartbeat_interval: Duration::from_secs(args.heartbeat_interval_secs),
+        client_timeout: Duration::from_secs(args.client_timeout_secs),
+        idle_timeout: Duration::from_secs(args.idle_timeout_secs),
+        protocol: Protocol::Text,
+        message_prefix: args.message_prefix,
+        slash_commands: args.slash_commands,
+    };
+
+    let json_config = SessionConfig {
+        heartbeat_interval: args
+            .json_heartbeat_interval_secs
+            .map_or(session_config.heartbeat_interval, Duration::from_secs),
+        client_timeout: args
+            .json_client_timeout_secs
+            .map_or(session_config.client_timeout, Duration::from_secs),
+        protocol: Protocol::Json,
+        ..session_config.clone()
+    };
+    let json_server = server.clone();
 
-    let _ = start_tcp_listener(address, server.clone()).await;
-    Ok(())
+    let (_, _, http_result) = futures::join!(
+        start_tcp_listener(listener, server.clone(), session_config),
+        async move {
+            if let Some(listener) = json_listener {
+                start_tcp_listener(listener, json_server, json_config).await
+            }
+        },
+        http_server
+    );
+    http_result
 }
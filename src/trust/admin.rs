@@ -0,0 +1,289 @@
+use super::{
+    attachments::AttachmentConfig,
+    response::chat_message,
+    room::{now_millis, ChatMessage, RoomError, RoomRetention},
+    server::{
+        contracts::{
+            DeleteRoomContract, ExportRoomContract, GetRetentionContract, ImportRoomContract,
+            SetRetentionContract,
+        },
+        TrustServer, TrustServerError,
+    },
+};
+use actix::Addr;
+use actix_web::{http::header::AUTHORIZATION, web, HttpRequest, HttpResponse};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Settings of the admin API.
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    /// Bearer token admin requests must carry; the admin API is disabled without one.
+    pub token: Option<String>,
+}
+
+/// Format of a room history export.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON record per line, which can be imported again.
+    #[default]
+    Jsonl,
+
+    /// Plain text transcript, for people to read.
+    Transcript,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// A line of a JSON Lines room history export.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryRecord {
+    /// Room metadata, the first line of an export.
+    Room {
+        room: String,
+        exported_at: u64,
+        messages: usize,
+    },
+
+    /// A message of the room history, oldest first.
+    Message(Box<ChatMessage>),
+}
+
+/// Export the history of a room with `GET /admin/rooms/{room}/history?format={jsonl|transcript}`.
+pub async fn export_history(
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AdminConfig>,
+) -> HttpResponse {
+    if let Some(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    let room_name = path_room_name(&req);
+    let export_req = ExportRoomContract {
+        room_name: room_name.clone(),
+    };
+
+    let messages = match server.send(export_req).await {
+        Ok(Ok(messages)) => messages,
+        response => return error_response(response.ok()),
+    };
+
+    let exported_at = now_millis();
+    match query.format {
+        ExportFormat::Jsonl => {
+            let header = HistoryRecord::Room {
+                room: room_name,
+                exported_at,
+                messages: messages.len(),
+            };
+
+            let records = std::iter::once(header)
+                .chain(
+                    messages
+                        .into_iter()
+                        .map(|message| HistoryRecord::Message(Box::new(message))),
+                )
+                .map(|record| serde_json::to_string(&record).unwrap_or_default() + "\n")
+                .collect::<String>();
+
+            HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .body(records)
+        }
+
+        ExportFormat::Transcript => {
+            let header = format!(
+                "Room {}: {} messages, exported at {}\n",
+                room_name,
+                messages.len(),
+                exported_at
+            );
+
+            let transcript = messages
+                .iter()
+                .map(|message| chat_message(message, true) + "\n")
+                .collect::<String>();
+
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .body(header + &transcript)
+        }
+    }
+}
+
+/// Import a JSON Lines export into a room without history with
+/// `POST /admin/rooms/{room}/history`, creating the room if needed.
+pub async fn import_history(
+    req: HttpRequest,
+    body: String,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AdminConfig>,
+) -> HttpResponse {
+    if let Some(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    let mut messages = Vec::new();
+    for (number, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(line) {
+            Ok(HistoryRecord::Message(message)) => messages.push(*message),
+            Ok(HistoryRecord::Room { .. }) => {}
+            Err(err) => {
+                return HttpResponse::BadRequest().body(format!("line {}: {}", number + 1, err))
+            }
+        }
+    }
+
+    let import_req = ImportRoomContract {
+        room_name: path_room_name(&req),
+        messages,
+    };
+
+    match server.send(import_req).await {
+        Ok(Ok(imported)) => HttpResponse::Ok().body(format!("imported {} messages\n", imported)),
+        response => error_response(response.ok()),
+    }
+}
+
+/// Drop a room without members and not on legal hold, with its history and
+/// shared files, with `DELETE /admin/rooms/{room}`.
+pub async fn delete_room(
+    req: HttpRequest,
+    server: web::Data<Addr<TrustServer>>,
+    attachments: web::Data<AttachmentConfig>,
+    config: web::Data<AdminConfig>,
+) -> HttpResponse {
+    if let Some(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    let delete_req = DeleteRoomContract {
+        room_name: path_room_name(&req),
+    };
+
+    let shared = match server.send(delete_req).await {
+        Ok(Ok(shared)) => shared,
+        response => return error_response(response.ok()),
+    };
+
+    for attachment in &shared {
+        let path = attachments.dir.join(&attachment.id);
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            warn!(path = %path.display(), error = %err, "Failed to remove attachment");
+        }
+    }
+
+    HttpResponse::NoContent().finish()
+}
+
+/// Get the retention settings of a room with `GET /admin/rooms/{room}/retention`.
+pub async fn get_retention(
+    req: HttpRequest,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AdminConfig>,
+) -> HttpResponse {
+    if let Some(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    let get_req = GetRetentionContract {
+        room_name: path_room_name(&req),
+    };
+
+    match server.send(get_req).await {
+        Ok(Ok(retention)) => HttpResponse::Ok().json(retention),
+        response => error_response(response.ok()),
+    }
+}
+
+/// Replace the retention settings of a room with `PUT /admin/rooms/{room}/retention`.
+pub async fn set_retention(
+    req: HttpRequest,
+    retention: web::Json<RoomRetention>,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AdminConfig>,
+) -> HttpResponse {
+    if let Some(response) = authorize(&req, &config) {
+        return response;
+    }
+
+    let set_req = SetRetentionContract {
+        room_name: path_room_name(&req),
+        retention: retention.into_inner(),
+    };
+
+    match server.send(set_req).await {
+        Ok(Ok(retention)) => HttpResponse::Ok().json(retention),
+        response => error_response(response.ok()),
+    }
+}
+
+/// Check the admin token of a request, returning the response to reject it with.
+fn authorize(req: &HttpRequest, config: &AdminConfig) -> Option<HttpResponse> {
+    let expected = match &config.token {
+        Some(token) => token,
+        None => return Some(HttpResponse::NotFound().finish()),
+    };
+
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if token.trim() == expected => None,
+        _ => Some(HttpResponse::Unauthorized().finish()),
+    }
+}
+
+/// Room an admin request is about, decoded from the raw request path.
+///
+/// The router leaves `%2F` and `%2B` encoded in path parameters, so rooms
+/// with `/` or `+` in their name would be unreachable through `web::Path`.
+fn path_room_name(req: &HttpRequest) -> String {
+    let segment = req
+        .uri()
+        .path()
+        .trim_start_matches("/admin/rooms/")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+/// Map the chat server's answer to an admin request to an HTTP error.
+fn error_response<T>(response: Option<Result<T, TrustServerError>>) -> HttpResponse {
+    match response {
+        Some(Err(TrustServerError::RoomNotFound(_))) => HttpResponse::NotFound().finish(),
+        Some(Err(TrustServerError::RoomError(RoomError::HistoryNotEmpty(room_name)))) => {
+            HttpResponse::Conflict().body(format!("room [{}] already has history\n", room_name))
+        }
+        Some(Err(err @ TrustServerError::InvalidRoomName(_))) => {
+            HttpResponse::BadRequest().body(format!("{}\n", err))
+        }
+        Some(Err(err @ TrustServerError::RoomInUse(_)))
+        | Some(Err(err @ TrustServerError::LegalHold(_))) => {
+            HttpResponse::Conflict().body(format!("{}\n", err))
+        }
+        Some(Err(err)) => {
+            warn!(error = %err, "Failed to handle admin request");
+            HttpResponse::InternalServerError().finish()
+        }
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
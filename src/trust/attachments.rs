@@ -0,0 +1,195 @@
+use super::{
+    room::{Attachment, RoomError},
+    server::{
+        contracts::{FetchAttachmentContract, ShareAttachmentContract},
+        TrustServer, TrustServerError,
+    },
+};
+use actix::Addr;
+use actix_web::{
+    http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE},
+    web, HttpRequest, HttpResponse,
+};
+use serde::Deserialize;
+use std::{path::PathBuf, str::FromStr};
+use tokio::fs;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Settings of files shared in rooms.
+#[derive(Debug, Clone)]
+pub struct AttachmentConfig {
+    /// Directory the files are stored in, named by their attachment id.
+    pub dir: PathBuf,
+
+    /// Largest file accepted, in bytes.
+    pub max_size: usize,
+
+    /// Content types accepted.
+    pub allowed_types: Vec<String>,
+
+    /// Base URL of the HTTP server, used in download URLs.
+    pub base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    name: String,
+}
+
+/// Upload a file with `POST /rooms/{room}/attachments?name={file name}`, and
+/// share it in the room.
+///
+/// The request is authenticated with the session's resume token as a bearer
+/// token, and the session must have joined the room.
+pub async fn upload_attachment(
+    req: HttpRequest,
+    room_name: web::Path<String>,
+    query: web::Query<UploadQuery>,
+    body: web::Bytes,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AttachmentConfig>,
+) -> HttpResponse {
+    let token = match bearer_token(&req) {
+        Some(token) => token,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if !config.allowed_types.contains(&content_type) {
+        return HttpResponse::UnsupportedMediaType().finish();
+    }
+
+    let name = match file_name(&query.name) {
+        Some(name) if !body.is_empty() => name,
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let attachment = Attachment {
+        url: format!("{}/attachments/{}", config.base_url, id),
+        id,
+        name,
+        size: body.len() as u64,
+        content_type,
+    };
+
+    let path = config.dir.join(&attachment.id);
+    if let Err(err) = fs::write(&path, &body).await {
+        warn!(path = %path.display(), error = %err, "Failed to store attachment");
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let share_req = ShareAttachmentContract {
+        token,
+        room_name: room_name.into_inner(),
+        attachment: attachment.clone(),
+    };
+
+    match server.send(share_req).await {
+        Ok(Ok(())) => HttpResponse::Created().json(attachment),
+        response => {
+            let _ = fs::remove_file(&path).await;
+            error_response(response.ok())
+        }
+    }
+}
+
+/// Download a file with `GET /attachments/{id}`.
+///
+/// The request is authenticated with the session's resume token as a bearer
+/// token, and the session must be a member of the room the file was shared in.
+pub async fn download_attachment(
+    req: HttpRequest,
+    attachment_id: web::Path<String>,
+    server: web::Data<Addr<TrustServer>>,
+    config: web::Data<AttachmentConfig>,
+) -> HttpResponse {
+    let token = match bearer_token(&req) {
+        Some(token) => token,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let fetch_req = FetchAttachmentContract {
+        token,
+        attachment_id: attachment_id.into_inner(),
+    };
+
+    let attachment = match server.send(fetch_req).await {
+        Ok(Ok(attachment)) => attachment,
+        response => return error_response(response.ok()),
+    };
+
+    match fs::read(config.dir.join(&attachment.id)).await {
+        Ok(content) => HttpResponse::Ok()
+            .content_type(attachment.content_type.as_str())
+            .insert_header((
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", attachment.name),
+            ))
+            .body(content),
+        Err(err) => {
+            warn!(attachment_id = %attachment.id, error = %err, "Failed to read attachment");
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
+
+/// Map the chat server's answer to an attachment request to an HTTP error.
+fn error_response<T>(response: Option<Result<T, TrustServerError>>) -> HttpResponse {
+    match response {
+        Some(Err(TrustServerError::InvalidResumeToken)) => HttpResponse::Unauthorized().finish(),
+        Some(Err(TrustServerError::RoomError(RoomError::AttachmentNotFound(_)))) => {
+            HttpResponse::NotFound().finish()
+        }
+        Some(Err(TrustServerError::RoomError(_))) => HttpResponse::Forbidden().finish(),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Get the resume token sent as a bearer token.
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    Some(token.to_string()).filter(|token| !token.is_empty())
+}
+
+/// Get a file name safe to show to users, without any directories.
+///
+/// Names with control characters or `<NL>` are rejected: the name is sent in
+/// chat frames and in the `Content-Disposition` header of downloads.
+fn file_name(name: &str) -> Option<String> {
+    if name.chars().any(char::is_control) || name.contains("<NL>") {
+        return None;
+    }
+
+    let name = PathBuf::from_str(name).ok()?;
+    let name = name.file_name()?.to_str()?.replace('"', "");
+    Some(name.chars().take(255).collect()).filter(|name: &String| !name.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_strips_directories_and_quotes() {
+        assert_eq!(file_name("notes.txt").as_deref(), Some("notes.txt"));
+        assert_eq!(file_name("../../etc/\"passwd\"").as_deref(), Some("passwd"));
+        assert_eq!(file_name("  "), None);
+    }
+
+    #[test]
+    fn file_name_rejects_control_characters_and_frame_separators() {
+        assert_eq!(file_name("a\r\nb.txt"), None);
+        assert_eq!(file_name("a\tb.txt"), None);
+        assert_eq!(file_name("a<NL>b.txt"), None);
+    }
+}
@@ -1,3 +1,4 @@
+use super::metrics;
 use bytes::{BufMut, BytesMut};
 use std::io;
 use tokio_util::codec::{Decoder, Encoder};
@@ -11,6 +12,10 @@ impl Decoder for TrustTcpCodec {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         if let Some(nl_index) = find_subsequence(src, b"<NL>") {
             let line = src.split_to(nl_index + 4);
+            if std::str::from_utf8(&line[..nl_index]).is_err() {
+                metrics::CODEC_ERRORS.inc();
+            }
+
             return Ok(Some(
                 String::from_utf8_lossy(&line[..nl_index])
                     .trim()
@@ -0,0 +1,159 @@
+use super::{room::RoomError, server::TrustServerError, user::CommandError};
+use serde::Serialize;
+use std::fmt;
+
+/// Stable code sent to a client along with every error, so clients can tell
+/// errors apart without parsing their human readable message.
+///
+/// Codes are part of the protocol: new ones may be added, but existing ones
+/// are never renamed or reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The line sent was empty.
+    EmptyCommand,
+    /// No command has the name sent.
+    UnknownCommand,
+    /// The arguments of a command don't match its usage.
+    InvalidArguments,
+    /// A room name isn't valid.
+    InvalidRoomName,
+    /// A username isn't valid.
+    InvalidUsername,
+    /// A message has no content.
+    EmptyMessage,
+    /// A reaction isn't a valid emoji.
+    InvalidReaction,
+    /// A search query or one of its filters isn't valid.
+    InvalidSearch,
+    /// The session isn't registered with the server yet.
+    NotConnected,
+    /// The session isn't known to the server.
+    InvalidUserId,
+    /// The session already joined a room.
+    DuplicateSession,
+    /// The session already joined a room, and can't join another one.
+    AlreadyInRoom,
+    /// The command needs the session to have joined a room.
+    NotInRoom,
+    /// No message in the room history has the id given.
+    MessageNotFound,
+    /// The user isn't allowed to change the message.
+    NotPermitted,
+    /// The reaction is already added or removed.
+    ReactionUnchanged,
+    /// No attachment has the id given.
+    AttachmentNotFound,
+    /// The room already has history.
+    HistoryNotEmpty,
+    /// A plugin rejected the message.
+    MessageRejected,
+    /// The server couldn't deliver a message.
+    DeliveryFailed,
+    /// The resume token is invalid or expired.
+    InvalidResumeToken,
+    /// The session to resume is still connected.
+    SessionInUse,
+    /// The session to resume was evicted; its queued messages were sent.
+    SessionExpired,
+    /// The operator password is wrong.
+    InvalidOperatorPassword,
+    /// The command needs the user to be a server operator.
+    NotServerOperator,
+    /// No room has the name given.
+    RoomNotFound,
+    /// No incoming webhook has the token given.
+    InvalidHookToken,
+    /// Too many requests were sent in a short time.
+    RateLimited,
+    /// The room still has members.
+    RoomInUse,
+    /// The room is on legal hold.
+    LegalHold,
+    /// The server failed to handle the command.
+    Internal,
+}
+
+impl ErrorCode {
+    /// Code as sent to clients, e.g. `NOT_IN_ROOM`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::EmptyCommand => "EMPTY_COMMAND",
+            ErrorCode::UnknownCommand => "UNKNOWN_COMMAND",
+            ErrorCode::InvalidArguments => "INVALID_ARGUMENTS",
+            ErrorCode::InvalidRoomName => "INVALID_ROOM_NAME",
+            ErrorCode::InvalidUsername => "INVALID_USERNAME",
+            ErrorCode::EmptyMessage => "EMPTY_MESSAGE",
+            ErrorCode::InvalidReaction => "INVALID_REACTION",
+            ErrorCode::InvalidSearch => "INVALID_SEARCH",
+            ErrorCode::NotConnected => "NOT_CONNECTED",
+            ErrorCode::InvalidUserId => "INVALID_USER_ID",
+            ErrorCode::DuplicateSession => "DUPLICATE_SESSION",
+            ErrorCode::AlreadyInRoom => "ALREADY_IN_ROOM",
+            ErrorCode::NotInRoom => "NOT_IN_ROOM",
+            ErrorCode::MessageNotFound => "MESSAGE_NOT_FOUND",
+            ErrorCode::NotPermitted => "NOT_PERMITTED",
+            ErrorCode::ReactionUnchanged => "REACTION_UNCHANGED",
+            ErrorCode::AttachmentNotFound => "ATTACHMENT_NOT_FOUND",
+            ErrorCode::HistoryNotEmpty => "HISTORY_NOT_EMPTY",
+            ErrorCode::MessageRejected => "MESSAGE_REJECTED",
+            ErrorCode::DeliveryFailed => "DELIVERY_FAILED",
+            ErrorCode::InvalidResumeToken => "INVALID_RESUME_TOKEN",
+            ErrorCode::SessionInUse => "SESSION_IN_USE",
+            ErrorCode::SessionExpired => "SESSION_EXPIRED",
+            ErrorCode::InvalidOperatorPassword => "INVALID_OPERATOR_PASSWORD",
+            ErrorCode::NotServerOperator => "NOT_SERVER_OPERATOR",
+            ErrorCode::RoomNotFound => "ROOM_NOT_FOUND",
+            ErrorCode::InvalidHookToken => "INVALID_HOOK_TOKEN",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::RoomInUse => "ROOM_IN_USE",
+            ErrorCode::LegalHold => "LEGAL_HOLD",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error sent to the client whose command failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientError {
+    pub code: ErrorCode,
+
+    /// Human readable explanation; unlike the code, it may change.
+    pub message: String,
+}
+
+impl ClientError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<CommandError> for ClientError {
+    fn from(error: CommandError) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
+impl From<RoomError> for ClientError {
+    fn from(error: RoomError) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
+impl From<TrustServerError> for ClientError {
+    fn from(error: TrustServerError) -> Self {
+        match error {
+            TrustServerError::RoomError(error) => error.into(),
+            error => Self::new(error.code(), error.to_string()),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::{
+    room::RoomError,
+    server::{contracts::IncomingHookContract, TrustServer, TrustServerError},
+};
+use actix::Addr;
+use actix_web::{http::header::CONTENT_TYPE, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use tracing::warn;
+
+/// JSON body of an incoming webhook request.
+#[derive(Debug, Deserialize)]
+struct HookPayload {
+    text: String,
+}
+
+/// Post a message to the room of an incoming webhook with
+/// `POST /hooks/{token}`, as plain text or as JSON (`{"text": "..."}`).
+pub async fn post_hook_message(
+    req: HttpRequest,
+    token: web::Path<String>,
+    body: String,
+    server: web::Data<Addr<TrustServer>>,
+) -> HttpResponse {
+    let is_json = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let content = if is_json {
+        match serde_json::from_str::<HookPayload>(&body) {
+            Ok(payload) => payload.text,
+            Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+        }
+    } else {
+        body
+    };
+
+    let content = single_line(&content);
+    if content.is_empty() {
+        return HttpResponse::BadRequest().body("message is empty");
+    }
+
+    let hook_req = IncomingHookContract {
+        token: token.into_inner(),
+        content,
+    };
+
+    match server.send(hook_req).await {
+        Ok(Ok(message)) => HttpResponse::Created().json(message),
+        Ok(Err(TrustServerError::InvalidHookToken)) => HttpResponse::NotFound().finish(),
+        Ok(Err(TrustServerError::HookRateLimited(_))) => {
+            HttpResponse::TooManyRequests().body("too many messages, try again later")
+        }
+        Ok(Err(TrustServerError::RoomError(RoomError::MessageRejected(reason)))) => {
+            HttpResponse::UnprocessableEntity().body(reason)
+        }
+        Ok(Err(err)) => {
+            warn!(error = %err, "Failed to post webhook message");
+            HttpResponse::InternalServerError().finish()
+        }
+        Err(err) => {
+            warn!(error = %err, "Failed to post webhook message");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Turn a posted text into a single line chat message: chat frames end with
+/// `<NL>` and a line break, so those, other control characters and runs of
+/// whitespace become single spaces.
+fn single_line(text: &str) -> String {
+    text.replace("<NL>", " ")
+        .replace(char::is_control, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_line_removes_frame_separators() {
+        assert_eq!(
+            single_line("build #12 passed<NL>\nalice: fake\r\n"),
+            "build #12 passed alice: fake"
+        );
+        assert_eq!(single_line(" tests:\t42  passed "), "tests: 42 passed");
+        assert_eq!(single_line("<NL>\n"), "");
+    }
+}
@@ -0,0 +1,97 @@
+use actix_web::HttpResponse;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_gauge, Encoder, Histogram, IntCounter,
+    IntGauge, TextEncoder,
+};
+
+lazy_static! {
+    /// Number of client sessions currently connected to the server.
+    pub static ref CONNECTED_SESSIONS: IntGauge = register_int_gauge!(
+        "trust_connected_sessions",
+        "Number of client sessions currently connected"
+    )
+    .unwrap();
+
+    /// Number of chat rooms currently open.
+    pub static ref ROOMS: IntGauge =
+        register_int_gauge!("trust_rooms", "Number of chat rooms currently open").unwrap();
+
+    /// Messages received from clients.
+    pub static ref MESSAGES_RECEIVED: IntCounter = register_int_counter!(
+        "trust_messages_received_total",
+        "Total number of messages received from clients"
+    )
+    .unwrap();
+
+    /// Messages written out to clients.
+    pub static ref MESSAGES_SENT: IntCounter = register_int_counter!(
+        "trust_messages_sent_total",
+        "Total number of messages sent to clients"
+    )
+    .unwrap();
+
+    /// Number of recipients a single room broadcast is delivered to.
+    pub static ref ROOM_FANOUT: Histogram = register_histogram!(
+        "trust_room_fanout_size",
+        "Number of recipients of a single room broadcast",
+        vec![1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0]
+    )
+    .unwrap();
+
+    /// Messages that could not be handed over to a recipient's mailbox.
+    pub static ref DROPPED_MESSAGES: IntCounter = register_int_counter!(
+        "trust_dropped_messages_total",
+        "Total number of messages dropped because the recipient mailbox rejected them"
+    )
+    .unwrap();
+
+    /// Sessions disconnected because the client stopped responding.
+    pub static ref HEARTBEAT_TIMEOUTS: IntCounter = register_int_counter!(
+        "trust_heartbeat_timeouts_total",
+        "Total number of sessions disconnected after a heartbeat timeout"
+    )
+    .unwrap();
+
+    /// Round-trip time between a heartbeat ping and the client's pong.
+    pub static ref HEARTBEAT_ROUND_TRIP: Histogram = register_histogram!(
+        "trust_heartbeat_round_trip_seconds",
+        "Round-trip time between a heartbeat ping and the matching pong",
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .unwrap();
+
+    /// Frames that could not be decoded or encoded cleanly.
+    pub static ref CODEC_ERRORS: IntCounter = register_int_counter!(
+        "trust_codec_errors_total",
+        "Total number of errors raised while decoding or encoding frames"
+    )
+    .unwrap();
+}
+
+/// Register every metric up front, so idle series are exported as zero.
+pub fn init() {
+    lazy_static::initialize(&CONNECTED_SESSIONS);
+    lazy_static::initialize(&ROOMS);
+    lazy_static::initialize(&MESSAGES_RECEIVED);
+    lazy_static::initialize(&MESSAGES_SENT);
+    lazy_static::initialize(&ROOM_FANOUT);
+    lazy_static::initialize(&DROPPED_MESSAGES);
+    lazy_static::initialize(&HEARTBEAT_TIMEOUTS);
+    lazy_static::initialize(&HEARTBEAT_ROUND_TRIP);
+    lazy_static::initialize(&CODEC_ERRORS);
+}
+
+/// HTTP handler exposing the metrics in the Prometheus text format.
+pub async fn metrics_handler() -> HttpResponse {
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return HttpResponse::InternalServerError().body(err.to_string());
+    }
+
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
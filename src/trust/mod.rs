@@ -1,4 +1,11 @@
+pub mod admin;
+pub mod attachments;
 pub mod codec;
+pub mod errors;
+pub mod hooks;
+pub mod metrics;
+pub mod plugins;
+pub mod pruner;
 pub mod response;
 pub mod room;
 pub mod server;
@@ -0,0 +1,136 @@
+mod webhooks;
+mod welcome;
+mod word_filter;
+
+pub use self::{webhooks::*, welcome::*, word_filter::*};
+use super::{
+    room::{ChatMessage, MessageKind},
+    server::{contracts::PluginContract, TrustServer},
+};
+use actix::Addr;
+use std::fmt;
+
+/// Extension hooked into the chat server, registered at startup with
+/// [`TrustServer::with_plugin`]. Hooks run on the chat server, so they
+/// should return quickly and leave slow work to a spawned task.
+pub trait Plugin {
+    /// Name of the plugin, used in logs.
+    fn name(&self) -> &str;
+
+    /// A client connected.
+    fn on_connect(&self, _handle: &PluginHandle, _user_id: &str) {}
+
+    /// A user joined a room.
+    fn on_join(&self, _handle: &PluginHandle, _room_name: &str, _user_id: &str, _username: &str) {}
+
+    /// A user left a room.
+    fn on_leave(&self, _handle: &PluginHandle, _room_name: &str, _user_id: &str, _username: &str) {}
+
+    /// A message is about to be broadcast to a room, or an edit of one or a
+    /// shared file's name is. The plugin may modify it, or reject it with a
+    /// reason sent back to its author.
+    fn before_broadcast(
+        &self,
+        _handle: &PluginHandle,
+        _message: &mut PendingMessage,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// A user's message was broadcast to a room.
+    fn on_message(&self, _handle: &PluginHandle, _message: &ChatMessage) {}
+
+    /// A client's connection dropped; its session may still be resumed.
+    fn on_disconnect(&self, _handle: &PluginHandle, _user_id: &str) {}
+}
+
+/// A message on its way to a room, before it is added to the room history.
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub room: String,
+
+    /// Session of the author; empty for messages nobody authored.
+    pub user_id: String,
+    pub username: String,
+    pub content: String,
+    pub kind: MessageKind,
+}
+
+/// Handle plugins send messages through. Messages are handled by the chat
+/// server after the current hook returns.
+#[derive(Debug, Clone)]
+pub struct PluginHandle {
+    server: Addr<TrustServer>,
+    plugin: String,
+}
+
+impl PluginHandle {
+    /// Send a notice to a single user.
+    pub fn message_user(&self, user_id: &str, text: &str) {
+        self.server.do_send(PluginContract::MessageUser {
+            user_id: user_id.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    /// Broadcast a notice to a room, from the plugin. It is kept in the room
+    /// history, but doesn't go through the plugins' `before_broadcast` hooks.
+    pub fn broadcast(&self, room_name: &str, content: &str) {
+        self.server.do_send(PluginContract::Broadcast {
+            room_name: room_name.to_string(),
+            username: self.plugin.clone(),
+            content: content.to_string(),
+        });
+    }
+}
+
+/// Plugins registered with the chat server, run in registration order.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<(Box<dyn Plugin>, Option<PluginHandle>)>,
+}
+
+impl Plugins {
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push((plugin, None));
+    }
+
+    /// Hand the plugins the address of the chat server, once it has started.
+    pub fn start(&mut self, server: Addr<TrustServer>) {
+        for (plugin, handle) in &mut self.plugins {
+            *handle = Some(PluginHandle {
+                server: server.clone(),
+                plugin: plugin.name().to_string(),
+            });
+        }
+    }
+
+    /// Run a hook of every started plugin.
+    pub fn run(&self, mut hook: impl FnMut(&dyn Plugin, &PluginHandle)) {
+        for (plugin, handle) in &self.plugins {
+            if let Some(handle) = handle {
+                hook(plugin.as_ref(), handle);
+            }
+        }
+    }
+
+    /// Run the `before_broadcast` hooks of every started plugin, stopping at
+    /// the first one rejecting the message.
+    pub fn before_broadcast(&self, message: &mut PendingMessage) -> Result<(), String> {
+        for (plugin, handle) in &self.plugins {
+            if let Some(handle) = handle {
+                plugin.before_broadcast(handle, message)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.plugins.iter().map(|(plugin, _)| plugin.name()))
+            .finish()
+    }
+}
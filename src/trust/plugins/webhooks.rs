@@ -0,0 +1,453 @@
+use super::{Plugin, PluginHandle};
+use crate::trust::room::{now_millis, ChatMessage};
+use awc::{
+    http::{header, Uri},
+    Client,
+};
+use hmac::{Hmac, Mac, NewMac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{collections::HashMap, io, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, time::sleep};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// URL a room's events are posted to, given as `{room}={url}`.
+#[derive(Debug, Clone)]
+pub struct RoomWebhook {
+    pub room: String,
+    pub url: String,
+}
+
+impl FromStr for RoomWebhook {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (room, url) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected {{room}}={{url}}, got [{}]", value))?;
+        validate_url(url)?;
+
+        Ok(Self {
+            room: room.to_string(),
+            url: url.to_string(),
+        })
+    }
+}
+
+/// Settings of outgoing webhooks.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// URLs each room's events are posted to.
+    pub urls: HashMap<String, Vec<String>>,
+
+    /// Key the HMAC-SHA256 signature of each request is computed with.
+    pub secret: String,
+
+    /// Number of times a delivery is attempted before it is dead-lettered.
+    pub max_attempts: u32,
+
+    /// Time waited before retrying a failed delivery the first time; doubled
+    /// after every attempt.
+    pub initial_backoff: Duration,
+
+    /// File deliveries which kept failing are appended to, one JSON object per line.
+    pub dead_letter_file: PathBuf,
+}
+
+/// Event posted to a room's webhooks.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WebhookEvent<'a> {
+    Message { message: &'a ChatMessage },
+    Join { room: &'a str, username: &'a str },
+    Leave { room: &'a str, username: &'a str },
+}
+
+/// Body of a webhook request.
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    /// Delivery identifier, the same across retries.
+    id: String,
+
+    /// Time the event happened, in milliseconds since the Unix epoch.
+    timestamp: u64,
+
+    #[serde(flatten)]
+    event: WebhookEvent<'a>,
+}
+
+/// Delivery which was given up on, recorded in the dead-letter file.
+#[derive(Debug, Serialize)]
+struct DeadLetter<'a> {
+    url: &'a str,
+    attempts: u32,
+    error: String,
+    failed_at: u64,
+    payload: serde_json::Value,
+}
+
+/// Plugin posting room events as signed JSON to the room's webhooks,
+/// retrying failed deliveries with exponential backoff.
+pub struct WebhookPlugin {
+    config: Arc<WebhookConfig>,
+}
+
+impl WebhookPlugin {
+    /// Time a single delivery attempt may take.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Longest wait between two delivery attempts.
+    const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    fn post(&self, room_name: &str, event: WebhookEvent) {
+        let urls = match self.config.urls.get(room_name) {
+            Some(urls) => urls,
+            None => return,
+        };
+
+        let payload = WebhookPayload {
+            id: Uuid::new_v4().to_string(),
+            timestamp: now_millis(),
+            event,
+        };
+        let body = match serde_json::to_string(&payload) {
+            Ok(body) => body,
+            Err(err) => return warn!(error = %err, "Failed to serialize webhook event"),
+        };
+
+        for url in urls {
+            actix::spawn(deliver(
+                self.config.clone(),
+                url.clone(),
+                payload.id.clone(),
+                body.clone(),
+            ));
+        }
+    }
+}
+
+impl Plugin for WebhookPlugin {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    fn on_join(&self, _: &PluginHandle, room_name: &str, _user_id: &str, username: &str) {
+        let event = WebhookEvent::Join {
+            room: room_name,
+            username,
+        };
+        self.post(room_name, event);
+    }
+
+    fn on_leave(&self, _: &PluginHandle, room_name: &str, _user_id: &str, username: &str) {
+        let event = WebhookEvent::Leave {
+            room: room_name,
+            username,
+        };
+        self.post(room_name, event);
+    }
+
+    fn on_message(&self, _: &PluginHandle, message: &ChatMessage) {
+        self.post(&message.room, WebhookEvent::Message { message });
+    }
+}
+
+/// Post an event to a webhook until it is accepted, dead-lettering it once
+/// every attempt failed.
+async fn deliver(config: Arc<WebhookConfig>, url: String, id: String, body: String) {
+    let client = Client::builder()
+        .timeout(WebhookPlugin::REQUEST_TIMEOUT)
+        .finish();
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 1;
+
+    loop {
+        match post_json(&client, &config.secret, &url, &id, &body).await {
+            Ok(()) => return debug!(url = %url, delivery = %id, attempt, "Delivered webhook"),
+            Err(err) if attempt < config.max_attempts => {
+                debug!(url = %url, delivery = %id, attempt, error = %err, "Retrying webhook");
+                sleep(backoff).await;
+                backoff = backoff.saturating_mul(2).min(WebhookPlugin::MAX_BACKOFF);
+                attempt += 1;
+            }
+            Err(err) => {
+                warn!(url = %url, delivery = %id, attempt, error = %err, "Giving up on webhook");
+                return dead_letter(&config, &url, attempt, err, &body).await;
+            }
+        }
+    }
+}
+
+/// Record a delivery which kept failing in the dead-letter file.
+async fn dead_letter(config: &WebhookConfig, url: &str, attempts: u32, err: io::Error, body: &str) {
+    let letter = DeadLetter {
+        url,
+        attempts,
+        error: err.to_string(),
+        failed_at: now_millis(),
+        payload: serde_json::from_str(body).unwrap_or_default(),
+    };
+
+    let mut line = serde_json::to_string(&letter).unwrap_or_default();
+    line.push('\n');
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.dead_letter_file)
+        .await;
+
+    if let Err(err) = match file {
+        Ok(mut file) => match file.write_all(line.as_bytes()).await {
+            // The write may still be in flight on the blocking pool until flushed.
+            Ok(()) => file.flush().await,
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    } {
+        warn!(url, error = %err, "Failed to record dead webhook delivery");
+    }
+}
+
+/// Compute the hex encoded HMAC-SHA256 signature of a request sent at
+/// `timestamp`, over `{timestamp}.{body}`; signing the time lets receivers
+/// reject replayed requests.
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => unreachable!("HMAC accepts keys of any length"),
+    };
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Send a signed JSON body to a webhook, succeeding on any 2xx response.
+async fn post_json(
+    client: &Client,
+    secret: &str,
+    url: &str,
+    id: &str,
+    body: &str,
+) -> io::Result<()> {
+    let timestamp = now_millis();
+    let response = client
+        .post(url)
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .insert_header(("X-Trust-Delivery", id))
+        .insert_header(("X-Trust-Timestamp", timestamp.to_string()))
+        .insert_header((
+            "X-Trust-Signature",
+            format!("sha256={}", sign(secret, timestamp, body)),
+        ))
+        .send_body(body.to_string())
+        .await
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(io::Error::other(format!(
+            "unexpected response [{}]",
+            status
+        ))),
+    }
+}
+
+/// Check a webhook URL is an absolute `http://` or `https://` URL.
+fn validate_url(url: &str) -> Result<(), String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|err| format!("invalid webhook URL [{}]: {}", url, err))?;
+
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http"), Some(_)) | (Some("https"), Some(_)) => Ok(()),
+        (Some("http"), None) | (Some("https"), None) => {
+            Err(format!("missing host in webhook URL [{}]", url))
+        }
+        _ => Err(format!(
+            "only http:// and https:// webhook URLs are supported, got [{}]",
+            url
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{rt::System, web, App, HttpRequest, HttpResponse, HttpServer};
+    use parking_lot::Mutex;
+    use std::time::Instant;
+
+    /// Request received by the stand-in webhook server.
+    struct Received {
+        at: Instant,
+        delivery: String,
+        timestamp: u64,
+        signature: String,
+        body: String,
+    }
+
+    /// Local webhook server, answering with `500` to the first `failures`
+    /// requests and `200` to the others.
+    #[derive(Default)]
+    struct StandIn {
+        failures: usize,
+        received: Mutex<Vec<Received>>,
+    }
+
+    /// Start a stand-in webhook server; returns its URL.
+    fn start_stand_in(stand_in: Arc<StandIn>) -> String {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::from(stand_in.clone()))
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+
+        actix_web::rt::spawn(async move {
+            let _ = server.run().await;
+        });
+        url
+    }
+
+    async fn receive(
+        request: HttpRequest,
+        body: String,
+        stand_in: web::Data<StandIn>,
+    ) -> HttpResponse {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let mut received = stand_in.received.lock();
+        received.push(Received {
+            at: Instant::now(),
+            delivery: header("X-Trust-Delivery"),
+            timestamp: header("X-Trust-Timestamp").parse().unwrap_or_default(),
+            signature: header("X-Trust-Signature"),
+            body,
+        });
+
+        match received.len() <= stand_in.failures {
+            true => HttpResponse::InternalServerError().finish(),
+            false => HttpResponse::Ok().finish(),
+        }
+    }
+
+    fn config(max_attempts: u32) -> WebhookConfig {
+        let dead_letter_file =
+            std::env::temp_dir().join(format!("trust-webhooks-{}.jsonl", Uuid::new_v4()));
+
+        WebhookConfig {
+            urls: HashMap::new(),
+            secret: "secret".to_string(),
+            max_attempts,
+            initial_backoff: Duration::from_millis(50),
+            dead_letter_file,
+        }
+    }
+
+    #[test]
+    fn sign_covers_the_timestamp_and_body() {
+        let body = r#"{"event":"join"}"#;
+        assert_eq!(
+            sign("secret", 1792399792302, body),
+            "ba5553d5e4a0d452eb42fc9af788c8f5b7d62e0e44b7a3efb674fc041ec16f55"
+        );
+        assert_ne!(
+            sign("secret", 1792399792303, body),
+            sign("secret", 1792399792302, body)
+        );
+        assert_ne!(
+            sign("other", 1792399792302, body),
+            sign("secret", 1792399792302, body)
+        );
+    }
+
+    #[test]
+    fn validate_url_accepts_http_and_https_only() {
+        assert!(validate_url("http://127.0.0.1:8080/trust").is_ok());
+        assert!(validate_url("https://example.com/trust").is_ok());
+        assert!(validate_url("ftp://example.com/trust").is_err());
+        assert!(validate_url("example.com/trust").is_err());
+    }
+
+    #[test]
+    fn deliver_retries_signed_requests_with_backoff() {
+        System::new().block_on(async {
+            let stand_in = Arc::new(StandIn {
+                failures: 2,
+                ..StandIn::default()
+            });
+            let url = start_stand_in(stand_in.clone());
+            let config = Arc::new(config(5));
+            let body = r#"{"event":"join","room":"rust","username":"alice"}"#;
+
+            deliver(
+                config.clone(),
+                url,
+                "delivery-1".to_string(),
+                body.to_string(),
+            )
+            .await;
+
+            let log = stand_in.received.lock();
+            assert_eq!(log.len(), 3);
+            for received in log.iter() {
+                assert_eq!(received.delivery, "delivery-1");
+                assert_eq!(received.body, body);
+                assert_eq!(
+                    received.signature,
+                    format!("sha256={}", sign("secret", received.timestamp, body))
+                );
+            }
+            assert!(log[1].at - log[0].at >= Duration::from_millis(50));
+            assert!(log[2].at - log[1].at >= Duration::from_millis(100));
+            assert!(!config.dead_letter_file.exists());
+        });
+    }
+
+    #[test]
+    fn deliver_dead_letters_once_every_attempt_failed() {
+        System::new().block_on(async {
+            let stand_in = Arc::new(StandIn {
+                failures: usize::MAX,
+                ..StandIn::default()
+            });
+            let url = start_stand_in(stand_in.clone());
+            let config = Arc::new(config(2));
+            let body = r#"{"event":"leave","room":"rust","username":"alice"}"#;
+
+            deliver(
+                config.clone(),
+                url.clone(),
+                "delivery-2".to_string(),
+                body.to_string(),
+            )
+            .await;
+            assert_eq!(stand_in.received.lock().len(), 2);
+
+            let letter = std::fs::read_to_string(&config.dead_letter_file).unwrap();
+            let _ = std::fs::remove_file(&config.dead_letter_file);
+            let letter: serde_json::Value = serde_json::from_str(letter.trim_end()).unwrap();
+            assert_eq!(letter["url"], url.as_str());
+            assert_eq!(letter["attempts"], 2);
+            assert_eq!(letter["payload"]["event"], "leave");
+            assert!(letter["error"].as_str().unwrap().contains("500"));
+        });
+    }
+}
@@ -0,0 +1,30 @@
+use super::{Plugin, PluginHandle};
+
+/// Plugin greeting users who join a room with a notice to the room.
+/// `{username}` and `{room}` in the greeting are replaced with the user's
+/// name and the room they joined.
+pub struct WelcomePlugin {
+    greeting: String,
+}
+
+impl WelcomePlugin {
+    pub fn new(greeting: &str) -> Self {
+        Self {
+            greeting: greeting.to_string(),
+        }
+    }
+}
+
+impl Plugin for WelcomePlugin {
+    fn name(&self) -> &str {
+        "welcome"
+    }
+
+    fn on_join(&self, handle: &PluginHandle, room_name: &str, _user_id: &str, username: &str) {
+        let greeting = self
+            .greeting
+            .replace("{username}", username)
+            .replace("{room}", room_name);
+        handle.broadcast(room_name, &greeting);
+    }
+}
@@ -0,0 +1,66 @@
+use super::{PendingMessage, Plugin, PluginHandle};
+use tracing::debug;
+
+/// Plugin masking blocked words in messages before they are broadcast, and
+/// letting their author know if they have a session.
+pub struct WordFilterPlugin {
+    /// Blocked words, lowercased.
+    words: Vec<String>,
+}
+
+impl WordFilterPlugin {
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_blocked(&self, word: &str) -> bool {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        self.words
+            .iter()
+            .any(|blocked| word.to_lowercase() == *blocked)
+    }
+}
+
+impl Plugin for WordFilterPlugin {
+    fn name(&self) -> &str {
+        "word_filter"
+    }
+
+    fn before_broadcast(
+        &self,
+        handle: &PluginHandle,
+        message: &mut PendingMessage,
+    ) -> Result<(), String> {
+        if !message
+            .content
+            .split_whitespace()
+            .any(|word| self.is_blocked(word))
+        {
+            return Ok(());
+        }
+
+        message.content = message
+            .content
+            .split(' ')
+            .map(|word| {
+                if self.is_blocked(word) {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        debug!(room = %message.room, user_id = %message.user_id, "Masked blocked words");
+        if !message.user_id.is_empty() {
+            handle.message_user(
+                &message.user_id,
+                "Blocked words in your message were masked",
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use super::server::{contracts::PruneHistoryContract, TrustServer};
+use actix::{Actor, Addr, AsyncContext, Context};
+use std::{path::PathBuf, time::Duration};
+use tracing::{info, warn};
+
+/// Background actor pruning room histories according to their retention
+/// policies, and removing the files shared with the pruned messages.
+pub struct Pruner {
+    server: Addr<TrustServer>,
+
+    /// Directory shared files are stored in.
+    attachments_dir: PathBuf,
+
+    /// Time between two prunes.
+    interval: Duration,
+}
+
+impl Pruner {
+    pub fn new(server: Addr<TrustServer>, attachments_dir: PathBuf, interval: Duration) -> Self {
+        Self {
+            server,
+            attachments_dir,
+            interval,
+        }
+    }
+
+    fn prune(&self) {
+        let server = self.server.clone();
+        let dir = self.attachments_dir.clone();
+
+        actix::spawn(async move {
+            let attachments = match server.send(PruneHistoryContract).await {
+                Ok(attachments) => attachments,
+                Err(err) => return warn!(error = %err, "Failed to prune room history"),
+            };
+
+            for attachment in &attachments {
+                if let Err(err) = tokio::fs::remove_file(dir.join(&attachment.id)).await {
+                    warn!(attachment = %attachment.id, error = %err, "Failed to remove pruned attachment");
+                }
+            }
+
+            if !attachments.is_empty() {
+                info!(
+                    attachments = attachments.len(),
+                    "Removed pruned attachments"
+                );
+            }
+        });
+    }
+}
+
+impl Actor for Pruner {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.interval, |pruner, _| pruner.prune());
+    }
+}
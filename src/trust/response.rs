@@ -1,28 +1,329 @@
-// Server logger
-#[macro_export]
-macro_rules! log {
-    ($($arg:tt)*) => ({
-        use colored::Colorize;
-        println!("{}", format!(":: TRUST (🔑) :: {}", format!($($arg)*).white()).green());
-    })
-}
+use super::{
+    errors::ClientError,
+    room::{
+        Attachment, ChatMessage, MessageKind, ParentRef, ReactionChange, ReactionCounts, SearchHit,
+    },
+    server::{contracts::PlainTextMessage, Presence, RoomMember},
+    user::{CommandHelp, Protocol},
+};
+use std::collections::BTreeMap;
 
 /// Format new user has joined message.
 pub fn user_left_message(username: &str) -> String {
-    format!("{} has left<NL>", &username)
+    format!("{} has left", &username)
 }
 
 /// Format message from user.
 pub fn new_user_message(username: &str, message: &str) -> String {
-    format!("{}: {}<NL>", username, message)
+    format!("{}: {}", username, message)
+}
+
+/// Format action performed by user.
+pub fn action_message(username: &str, action: &str) -> String {
+    format!("* {} {}", username, action)
+}
+
+/// Format notice from user.
+pub fn notice_message(username: &str, notice: &str) -> String {
+    format!("-{}- {}", username, notice)
+}
+
+/// Format file shared by user.
+pub fn attachment_message(username: &str, attachment: &Attachment) -> String {
+    format!(
+        "{} shared {} ({} bytes): {}",
+        username, attachment.name, attachment.size, attachment.url
+    )
+}
+
+/// Format reply from user, quoting the message it replies to.
+pub fn new_user_reply(username: &str, parent: &ParentRef, message: &str) -> String {
+    format!(
+        "{} (re #{} {}: \"{}\"): {}",
+        username, parent.seq, parent.username, parent.excerpt, message
+    )
+}
+
+/// Format a chat message, optionally prefixed with its sequence number, id and timestamp.
+pub fn chat_message(message: &ChatMessage, with_prefix: bool) -> String {
+    let content = if message.deleted {
+        "(message deleted)"
+    } else {
+        &message.content
+    };
+
+    let text = match (&message.parent, message.kind, &message.attachment) {
+        (_, _, Some(attachment)) if !message.deleted => {
+            attachment_message(&message.username, attachment)
+        }
+        (Some(parent), _, _) => new_user_reply(&message.username, parent, content),
+        (None, MessageKind::Normal, _) => new_user_message(&message.username, content),
+        (None, MessageKind::Action, _) => action_message(&message.username, content),
+        (None, MessageKind::Notice, _) => notice_message(&message.username, content),
+        (None, MessageKind::System, _) => content.to_string(),
+    };
+
+    let text = match message.reactions.is_empty() {
+        true => text,
+        false => format!("{} {}", text, reactions(&message.reaction_counts())),
+    };
+
+    if !with_prefix {
+        return text;
+    }
+
+    format!(
+        "[#{} {} {}] {}",
+        message.seq, message.id, message.timestamp, text
+    )
+}
+
+/// Format reaction counts of a message, e.g. `[👍 2 🎉 1]`.
+fn reactions(counts: &ReactionCounts) -> String {
+    let counts = counts
+        .iter()
+        .map(|(emoji, count)| format!("{} {}", emoji, count))
+        .collect::<Vec<_>>();
+
+    format!("[{}]", counts.join(" "))
+}
+
+/// Format reaction added or removed notice.
+pub fn reaction_changed_message(change: &ReactionChange) -> String {
+    let action = match change.added {
+        true => "reacted with",
+        false => "took back",
+    };
+
+    format!(
+        "{} {} {} on message #{} {}",
+        change.username,
+        action,
+        change.emoji,
+        change.seq,
+        reactions(&change.reactions)
+    )
+}
+
+/// Format notice of a message mentioning the user.
+pub fn mention_message(message: &ChatMessage) -> String {
+    format!(
+        "{} mentioned you in {} (#{}): {}",
+        message.username, message.room, message.seq, message.content
+    )
+}
+
+/// Format typing indicator.
+pub fn typing_message(username: &str, typing: bool) -> String {
+    match typing {
+        true => format!("{} is typing…", username),
+        false => format!("{} stopped typing", username),
+    }
+}
+
+/// Format read receipt.
+pub fn read_receipt_message(username: &str, seq: u64) -> String {
+    format!("{} read up to message #{}", username, seq)
+}
+
+/// Format unread message counts per room.
+pub fn unread_message(rooms: &BTreeMap<String, usize>) -> String {
+    let rooms = rooms
+        .iter()
+        .map(|(room, count)| format!("{} {}", room, count))
+        .collect::<Vec<_>>();
+
+    format!("UNREAD {}", rooms.join(" "))
+}
+
+/// Format confirmation of the user marking themselves away.
+pub fn away_message() -> String {
+    "You are now marked as away".to_string()
+}
+
+/// Format confirmation of the user marking themselves back.
+pub fn back_message() -> String {
+    "You are no longer marked as away".to_string()
+}
+
+/// Format the members of a room and whether they are at their keyboard.
+pub fn who_message(room: &str, members: &[RoomMember]) -> String {
+    let members = members
+        .iter()
+        .map(|member| match &member.presence {
+            Presence::Online => member.username.clone(),
+            Presence::Idle => format!("{} (idle)", member.username),
+            Presence::Away { reason: None } => format!("{} (away)", member.username),
+            Presence::Away {
+                reason: Some(reason),
+            } => format!("{} (away: {})", member.username, reason),
+        })
+        .collect::<Vec<_>>();
+
+    format!("{} members: {}", room, members.join(", "))
+}
+
+/// Format confirmation of the user becoming a server operator.
+pub fn oper_message() -> String {
+    "You are now a server operator".to_string()
+}
+
+/// Format message of the day, one `MOTD` line per line of text.
+pub fn motd_message(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("MOTD {}", line))
+        .collect::<Vec<_>>()
+        .join("<NL>\n")
+}
+
+/// Format notice from a server operator.
+pub fn server_notice_message(text: &str) -> String {
+    format!("*** NOTICE {}", text)
+}
+
+/// Format a page of search results, one hit per line.
+pub fn search_results_message(room: &str, page: usize, total: usize, hits: &[SearchHit]) -> String {
+    let header = format!("SEARCH {} {} hits, page {}", room, total, page);
+    let hits = hits.iter().map(|hit| {
+        format!(
+            "#{} {} {} {}: {}",
+            hit.seq, hit.id, hit.timestamp, hit.username, hit.context
+        )
+    });
+
+    std::iter::once(header)
+        .chain(hits)
+        .collect::<Vec<_>>()
+        .join("<NL>\n")
+}
+
+/// Format message edited notice.
+pub fn message_edited_message(message: &ChatMessage) -> String {
+    format!(
+        "{} edited message #{}: {}",
+        message.username, message.seq, message.content
+    )
+}
+
+/// Format message deleted notice.
+pub fn message_deleted_message(seq: u64, deleted_by: &str) -> String {
+    format!("message #{} was deleted by {}", seq, deleted_by)
 }
 
 /// Format new user has joined message.
 pub fn user_joined_message(username: &str) -> String {
-    format!("{} has joined<NL>", &username)
+    format!("{} has joined", &username)
 }
 
-/// Format new user has joined message.
-pub fn error_message() -> String {
-    "ERROR<NL>".to_string()
+/// Format the token a client can use to resume its session.
+pub fn resume_token_message(token: &str) -> String {
+    format!("RESUME_TOKEN {}", token)
+}
+
+/// Format a message queued while the user's session was evicted, with when
+/// it was sent.
+pub fn queued_message(sent_at: u64, text: &str) -> String {
+    format!("[{}] {}", sent_at, text)
+}
+
+/// Format session resumed message.
+pub fn session_resumed_message() -> String {
+    "RESUMED".to_string()
+}
+
+/// Format heartbeat ping message.
+pub fn ping_message(token: &str) -> String {
+    format!("PING {}", token)
+}
+
+/// Format heartbeat pong message.
+pub fn pong_message(token: &str) -> String {
+    format!("PONG {}", token)
+}
+
+/// Format an error about a command the user sent, e.g.
+/// `ERROR NOT_IN_ROOM user [...] has not joined a room`.
+pub fn error_message(error: &ClientError) -> String {
+    format!("ERROR {} {}", error.code, error.message)
+}
+
+/// Format the reply to a tagged command which was handled.
+pub fn ok_message(tag: &str) -> String {
+    format!("OK {}", tag)
+}
+
+/// Format the reply to a tagged command which failed.
+pub fn err_message(tag: &str, error: &ClientError) -> String {
+    format!("ERR {} {} {}", tag, error.code, error.message)
+}
+
+/// Format help about commands, one line per command.
+pub fn help_message(commands: &[CommandHelp]) -> String {
+    commands
+        .iter()
+        .map(|command| {
+            let aliases = if command.aliases.is_empty() {
+                String::new()
+            } else {
+                format!("aliases: {}; ", command.aliases.join(", "))
+            };
+
+            format!(
+                "HELP {} - {} ({}for {})",
+                command.usage, command.help, aliases, command.permission
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("<NL>\n")
+}
+
+/// Render a message into a frame of the session's protocol.
+pub fn render_message(message: &PlainTextMessage, protocol: Protocol, with_prefix: bool) -> String {
+    match protocol {
+        Protocol::Json => serde_json::to_string(message).unwrap_or_default() + "\n",
+        Protocol::Text => format!("{}<NL>\n", render_text(message, with_prefix)),
+    }
+}
+
+/// Render a message as plain text, without the frame terminator.
+fn render_text(message: &PlainTextMessage, with_prefix: bool) -> String {
+    match message {
+        PlainTextMessage::Text { text } => text.clone(),
+        PlainTextMessage::Error(error) => error_message(error),
+        PlainTextMessage::Ok { tag } => ok_message(tag),
+        PlainTextMessage::Err { tag, error } => err_message(tag, error),
+        PlainTextMessage::Motd { text } => motd_message(text),
+        PlainTextMessage::ServerNotice { text } => server_notice_message(text),
+        PlainTextMessage::Message(message) => chat_message(message, with_prefix),
+        PlainTextMessage::Thread { messages, .. } | PlainTextMessage::History { messages, .. } => {
+            messages
+                .iter()
+                .map(|message| chat_message(message, with_prefix))
+                .collect::<Vec<_>>()
+                .join("<NL>\n")
+        }
+        PlainTextMessage::Mention(message) => mention_message(message),
+        PlainTextMessage::Queued { sent_at, message } => {
+            queued_message(*sent_at, &render_text(message, with_prefix))
+        }
+        PlainTextMessage::MessageEdited(message) => message_edited_message(message),
+        PlainTextMessage::MessageDeleted {
+            seq, deleted_by, ..
+        } => message_deleted_message(*seq, deleted_by),
+        PlainTextMessage::Typing {
+            username, typing, ..
+        } => typing_message(username, *typing),
+        PlainTextMessage::ReadReceipt { username, seq, .. } => read_receipt_message(username, *seq),
+        PlainTextMessage::Unread { rooms } => unread_message(rooms),
+        PlainTextMessage::Who { room, members } => who_message(room, members),
+        PlainTextMessage::SearchResults {
+            room,
+            page,
+            total,
+            hits,
+            ..
+        } => search_results_message(room, *page, *total, hits),
+        PlainTextMessage::ReactionChanged(change) => reaction_changed_message(change),
+        PlainTextMessage::Help { commands } => help_message(commands),
+    }
 }
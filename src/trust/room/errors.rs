@@ -1,15 +1,77 @@
-use crate::trust::server::{contracts::PlainTextMessage, TrustServerError};
+use crate::trust::{
+    errors::ErrorCode,
+    server::{contracts::PlainTextMessage, TrustServerError},
+};
 use actix::prelude::SendError;
+use std::fmt;
 
 /// Chat room Error.
 #[derive(Debug)]
 pub enum RoomError {
-    NoServer,
     InvalidUserId(String),
     DuplicateSessionId(String),
+    AlreadyInRoom(String),
+    NotInRoom(String),
+    MessageNotFound(String),
+    NotPermitted(String),
+    ReactionUnchanged(String),
+    AttachmentNotFound(String),
+    HistoryNotEmpty(String),
+    MessageRejected(String),
     FailedToSend(SendError<PlainTextMessage>),
 }
 
+impl RoomError {
+    /// Code of the error sent to clients.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RoomError::InvalidUserId(_) => ErrorCode::InvalidUserId,
+            RoomError::DuplicateSessionId(_) => ErrorCode::DuplicateSession,
+            RoomError::AlreadyInRoom(_) => ErrorCode::AlreadyInRoom,
+            RoomError::NotInRoom(_) => ErrorCode::NotInRoom,
+            RoomError::MessageNotFound(_) => ErrorCode::MessageNotFound,
+            RoomError::NotPermitted(_) => ErrorCode::NotPermitted,
+            RoomError::ReactionUnchanged(_) => ErrorCode::ReactionUnchanged,
+            RoomError::AttachmentNotFound(_) => ErrorCode::AttachmentNotFound,
+            RoomError::HistoryNotEmpty(_) => ErrorCode::HistoryNotEmpty,
+            RoomError::MessageRejected(_) => ErrorCode::MessageRejected,
+            RoomError::FailedToSend(_) => ErrorCode::DeliveryFailed,
+        }
+    }
+}
+
+impl fmt::Display for RoomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomError::InvalidUserId(user_id) => write!(f, "invalid user id [{}]", user_id),
+            RoomError::DuplicateSessionId(user_id) => {
+                write!(f, "session [{}] is already in the room", user_id)
+            }
+            RoomError::AlreadyInRoom(room_name) => {
+                write!(f, "already joined room [{}]", room_name)
+            }
+            RoomError::NotInRoom(user_id) => write!(f, "user [{}] has not joined a room", user_id),
+            RoomError::MessageNotFound(message_id) => {
+                write!(f, "message [{}] not found", message_id)
+            }
+            RoomError::NotPermitted(user_id) => {
+                write!(f, "user [{}] is not permitted to do that", user_id)
+            }
+            RoomError::ReactionUnchanged(emoji) => {
+                write!(f, "reaction [{}] is already in that state", emoji)
+            }
+            RoomError::AttachmentNotFound(attachment_id) => {
+                write!(f, "attachment [{}] not found", attachment_id)
+            }
+            RoomError::HistoryNotEmpty(room_name) => {
+                write!(f, "room [{}] already has history", room_name)
+            }
+            RoomError::MessageRejected(reason) => write!(f, "message rejected: {}", reason),
+            RoomError::FailedToSend(err) => write!(f, "failed to deliver message: {}", err),
+        }
+    }
+}
+
 impl From<RoomError> for TrustServerError {
     fn from(error: RoomError) -> Self {
         TrustServerError::RoomError(error)
@@ -0,0 +1,259 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Users (by session) who reacted to a message, keyed by emoji.
+pub type Reactions = BTreeMap<String, BTreeSet<String>>;
+
+/// Number of users who reacted to a message, keyed by emoji.
+pub type ReactionCounts = BTreeMap<String, usize>;
+
+/// Kind of a chat message, deciding how clients present it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// Regular message sent by a user.
+    #[default]
+    Normal,
+
+    /// Action performed by the user, sent with `ME` (e.g. `* alice waves`).
+    Action,
+
+    /// Notice which bots should never reply to automatically.
+    Notice,
+
+    /// Message generated by the server, such as a user joining the room.
+    System,
+}
+
+impl MessageKind {
+    fn is_normal(&self) -> bool {
+        *self == MessageKind::Normal
+    }
+}
+
+/// A chat message broadcast to a room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Globally unique message identifier.
+    pub id: String,
+
+    /// Monotonically increasing sequence number within the room.
+    pub seq: u64,
+
+    /// Server time the message was received, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    pub room: String,
+    pub username: String,
+    pub content: String,
+
+    /// Kind of the message; omitted for normal messages.
+    #[serde(default, skip_serializing_if = "MessageKind::is_normal")]
+    pub kind: MessageKind,
+
+    /// Message this one replies to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<ParentRef>,
+
+    /// Server time of the last edit, in milliseconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<u64>,
+
+    /// Whether the message was retracted; its content is cleared when it is.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+
+    /// Reactions to the message, sent to clients as counts per emoji.
+    #[serde(
+        default,
+        serialize_with = "serialize_reactions",
+        deserialize_with = "deserialize_reactions",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub reactions: Reactions,
+
+    /// File shared with the message; its content is the file name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+
+    /// Usernames of room members mentioned with `@username`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+
+    /// Session of the user who sent the message.
+    #[serde(skip)]
+    pub author_id: String,
+}
+
+impl ChatMessage {
+    pub fn new(
+        seq: u64,
+        room: &str,
+        author_id: &str,
+        username: &str,
+        content: &str,
+        kind: MessageKind,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            seq,
+            timestamp: now_millis(),
+            room: room.to_string(),
+            username: username.to_string(),
+            content: content.to_string(),
+            kind,
+            parent: None,
+            edited_at: None,
+            deleted: false,
+            reactions: Reactions::default(),
+            attachment: None,
+            mentions: Vec::new(),
+            author_id: author_id.to_string(),
+        }
+    }
+
+    /// Replace the content of the message.
+    pub fn edit(&mut self, content: &str) {
+        self.content = content.to_string();
+        self.edited_at = Some(now_millis());
+    }
+
+    /// Retract the message, clearing its content, reactions and attachment.
+    pub fn delete(&mut self) {
+        self.content.clear();
+        self.reactions.clear();
+        self.attachment = None;
+        self.deleted = true;
+    }
+
+    /// Add a user's reaction to the message; returns false if they had already reacted so.
+    pub fn react(&mut self, user_id: &str, emoji: &str) -> bool {
+        self.reactions
+            .entry(emoji.to_string())
+            .or_default()
+            .insert(user_id.to_string())
+    }
+
+    /// Remove a user's reaction from the message; returns false if there was none.
+    pub fn unreact(&mut self, user_id: &str, emoji: &str) -> bool {
+        let removed = match self.reactions.get_mut(emoji) {
+            Some(users) => users.remove(user_id),
+            None => false,
+        };
+
+        self.reactions.retain(|_, users| !users.is_empty());
+        removed
+    }
+
+    /// Number of users who reacted to the message, per emoji.
+    pub fn reaction_counts(&self) -> ReactionCounts {
+        reaction_counts(&self.reactions)
+    }
+}
+
+/// Get the words following an `@` in a message, which may mention a user.
+pub fn parse_mentions(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .filter(|username| !username.is_empty())
+}
+
+fn reaction_counts(reactions: &Reactions) -> ReactionCounts {
+    reactions
+        .iter()
+        .map(|(emoji, users)| (emoji.clone(), users.len()))
+        .collect()
+}
+
+fn serialize_reactions<S: Serializer>(
+    reactions: &Reactions,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    reaction_counts(reactions).serialize(serializer)
+}
+
+/// Read reaction counts back (e.g. from a history export); who reacted is
+/// not exported, so each reaction is attributed to a placeholder session.
+fn deserialize_reactions<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Reactions, D::Error> {
+    let counts = ReactionCounts::deserialize(deserializer)?;
+    let reactions = counts
+        .into_iter()
+        .map(|(emoji, count)| {
+            let users = (1..=count).map(|n| format!("imported-{}", n)).collect();
+            (emoji, users)
+        })
+        .collect();
+
+    Ok(reactions)
+}
+
+/// Reference to the message a reply responds to, quoting the start of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParentRef {
+    pub id: String,
+    pub seq: u64,
+    pub username: String,
+    pub excerpt: String,
+}
+
+impl ParentRef {
+    /// Maximum number of characters quoted from the parent message.
+    const EXCERPT_LENGTH: usize = 50;
+
+    pub fn of(message: &ChatMessage) -> Self {
+        let mut excerpt: String = message.content.chars().take(Self::EXCERPT_LENGTH).collect();
+        if excerpt.len() < message.content.len() {
+            excerpt.push('…');
+        }
+
+        Self {
+            id: message.id.clone(),
+            seq: message.seq,
+            username: message.username.clone(),
+            excerpt,
+        }
+    }
+}
+
+/// Current server time in milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// A user adding or removing a reaction to a message.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReactionChange {
+    pub room: String,
+    pub id: String,
+    pub seq: u64,
+    pub username: String,
+    pub emoji: String,
+    pub added: bool,
+
+    /// Reaction counts of the message after the change.
+    pub reactions: ReactionCounts,
+}
+
+/// A file shared in a room, downloadable by its members.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+
+    /// Size of the file in bytes.
+    pub size: u64,
+    pub content_type: String,
+
+    /// Where room members can download the file.
+    pub url: String,
+}
@@ -1,32 +1,497 @@
 mod errors;
+mod message;
+mod retention;
+mod search;
 
-pub use self::errors::*;
-use crate::trust::server::contracts::PlainTextMessage;
-use crate::trust::server::{TrustServer, UserSessionId};
-use actix::prelude::*;
+pub use self::{errors::*, message::*, retention::*, search::*};
+use crate::trust::metrics;
+use crate::trust::server::{contracts::PlainTextMessage, UserInfo, UserSessionId};
 use parking_lot::RwLock;
-use std::{collections::HashMap, rc::Weak};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
 
 type Username = String;
 
-#[derive(Message, Debug)]
-#[rtype(result = "()")]
+#[derive(Debug)]
 pub struct Room {
-    server: Weak<TrustServer>,
+    name: String,
     store: RwLock<HashMap<UserSessionId, Username>>,
+    operators: RwLock<HashSet<UserSessionId>>,
+    history: RwLock<VecDeque<ChatMessage>>,
+    index: RwLock<SearchIndex>,
+    last_seq: AtomicU64,
+
+    /// Members currently typing, with when they were last seen typing and
+    /// when that was last broadcast.
+    typing: RwLock<HashMap<UserSessionId, (Instant, Instant)>>,
+
+    /// Sequence number of the last message read under each username.
+    ///
+    /// There are no accounts, so usernames are the only identity markers
+    /// can be kept against: any session joining with a username shares (and
+    /// moves) the markers of everyone who used it before.
+    read_markers: RwLock<HashMap<Username, u64>>,
+
+    /// Files shared in the room, by id.
+    attachments: RwLock<HashMap<String, Attachment>>,
+
+    /// Retention limits overriding the server-wide policy, and the legal hold.
+    retention: RwLock<RoomRetention>,
 }
 
 impl Room {
-    pub fn new(server: Weak<TrustServer>) -> Self {
+    /// Maximum number of messages kept in the room history.
+    const HISTORY_CAPACITY: usize = 1000;
+
+    /// Minimum time between two typing indicators broadcast for a member.
+    const TYPING_RATE_LIMIT: Duration = Duration::from_secs(2);
+
+    pub fn new(name: &str) -> Self {
         Self {
-            server,
+            name: name.to_string(),
             store: RwLock::default(),
+            operators: RwLock::default(),
+            history: RwLock::default(),
+            index: RwLock::default(),
+            last_seq: AtomicU64::new(0),
+            typing: RwLock::default(),
+            read_markers: RwLock::default(),
+            attachments: RwLock::default(),
+            retention: RwLock::default(),
+        }
+    }
+
+    /// Create a new message in the room, optionally replying to an earlier
+    /// one, assigning it the next sequence number and appending it to the
+    /// room history. Returns the file shared with the message the full
+    /// history dropped to make room, if any, so that it can be removed.
+    pub fn new_message(
+        &self,
+        author_id: &str,
+        username: &str,
+        content: &str,
+        kind: MessageKind,
+        parent_id: Option<&str>,
+    ) -> Result<(ChatMessage, Option<Attachment>), RoomError> {
+        let mut history = self.history.write();
+        let parent = match parent_id {
+            Some(parent_id) => history
+                .iter()
+                .find(|message| message.id == parent_id && !message.deleted)
+                .map(ParentRef::of)
+                .ok_or_else(|| RoomError::MessageNotFound(parent_id.to_string()))
+                .map(Some)?,
+            None => None,
+        };
+
+        let seq = self.last_seq.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut message = ChatMessage::new(seq, &self.name, author_id, username, content, kind);
+        self.typing.write().remove(author_id);
+        message.parent = parent;
+        message.mentions = self.members_mentioned(content);
+
+        let mut index = self.index.write();
+        let mut evicted = None;
+        if history.len() == Self::HISTORY_CAPACITY {
+            if let Some(dropped) = history.pop_front() {
+                index.remove(dropped.seq, &dropped.content);
+                evicted = dropped.attachment;
+            }
+        }
+
+        if let Some(attachment) = &evicted {
+            self.attachments.write().remove(&attachment.id);
+        }
+
+        if kind != MessageKind::System {
+            index.add(message.seq, &message.content);
+        }
+
+        history.push_back(message.clone());
+        Ok((message, evicted))
+    }
+
+    /// Share a file in the room with a new message naming it. Like
+    /// [`Room::new_message`], returns the file of the message dropped from
+    /// the full history, if any.
+    pub fn share_attachment(
+        &self,
+        author_id: &str,
+        username: &str,
+        attachment: Attachment,
+    ) -> Result<(ChatMessage, Option<Attachment>), RoomError> {
+        let (mut message, evicted) = self.new_message(
+            author_id,
+            username,
+            &attachment.name,
+            MessageKind::Normal,
+            None,
+        )?;
+        message.attachment = Some(attachment.clone());
+
+        let mut history = self.history.write();
+        if let Some(stored) = history
+            .iter_mut()
+            .rev()
+            .find(|stored| stored.id == message.id)
+        {
+            stored.attachment = message.attachment.clone();
+        }
+
+        self.attachments
+            .write()
+            .insert(attachment.id.clone(), attachment);
+        Ok((message, evicted))
+    }
+
+    /// Get a file shared in the room.
+    pub fn attachment(&self, attachment_id: &str) -> Result<Attachment, RoomError> {
+        self.attachments
+            .read()
+            .get(attachment_id)
+            .cloned()
+            .ok_or_else(|| RoomError::AttachmentNotFound(attachment_id.to_string()))
+    }
+
+    /// Get every file shared in the room.
+    pub fn attachments(&self) -> Vec<Attachment> {
+        self.attachments.read().values().cloned().collect()
+    }
+
+    /// Add or remove a user's reaction to a message in the room history.
+    pub fn react(
+        &self,
+        message_id: &str,
+        user_id: &str,
+        emoji: &str,
+        add: bool,
+    ) -> Result<ChatMessage, RoomError> {
+        let mut history = self.history.write();
+        let message = history
+            .iter_mut()
+            .find(|message| message.id == message_id && !message.deleted)
+            .ok_or_else(|| RoomError::MessageNotFound(message_id.to_string()))?;
+
+        let changed = match add {
+            true => message.react(user_id, emoji),
+            false => message.unreact(user_id, emoji),
+        };
+
+        if !changed {
+            return Err(RoomError::ReactionUnchanged(emoji.to_string()));
+        }
+
+        Ok(message.clone())
+    }
+
+    /// Fill the history of a room which has none yet, e.g. from an export.
+    /// Only the latest messages fitting in the history are kept, and new
+    /// messages are numbered after both them and any the room had before.
+    pub fn import(&self, mut messages: Vec<ChatMessage>) -> Result<usize, RoomError> {
+        let mut history = self.history.write();
+        if !history.is_empty() {
+            return Err(RoomError::HistoryNotEmpty(self.name.clone()));
+        }
+
+        messages.sort_by_key(|message| message.seq);
+
+        let mut index = self.index.write();
+        let mut attachments = self.attachments.write();
+        let skip = messages.len().saturating_sub(Self::HISTORY_CAPACITY);
+
+        for mut message in messages.into_iter().skip(skip) {
+            message.room = self.name.clone();
+            if message.kind != MessageKind::System && !message.deleted {
+                index.add(message.seq, &message.content);
+            }
+
+            if let Some(attachment) = &message.attachment {
+                attachments.insert(attachment.id.clone(), attachment.clone());
+            }
+
+            history.push_back(message);
         }
+
+        let last_seq = history
+            .back()
+            .map(|message| message.seq)
+            .unwrap_or_default();
+        self.last_seq.fetch_max(last_seq, Ordering::SeqCst);
+        Ok(history.len())
+    }
+
+    /// Get the retention settings of the room.
+    pub fn retention(&self) -> RoomRetention {
+        *self.retention.read()
+    }
+
+    /// Replace the retention settings of the room.
+    pub fn set_retention(&self, retention: RoomRetention) {
+        *self.retention.write() = retention;
+    }
+
+    /// Drop the messages the room's retention policy, falling back to the
+    /// server-wide one, no longer keeps, unless the room is on legal hold.
+    /// Returns the files shared with the dropped messages.
+    pub fn prune(&self, fallback: RetentionPolicy, now: u64) -> Vec<Attachment> {
+        let retention = self.retention();
+        if retention.legal_hold {
+            return Vec::new();
+        }
+
+        let policy = retention.policy.or(fallback);
+        let oldest = policy
+            .max_age_secs
+            .map(|max_age| now.saturating_sub(max_age.saturating_mul(1000)));
+
+        let mut history = self.history.write();
+        let mut index = self.index.write();
+        let mut attachments = self.attachments.write();
+        let mut pruned = Vec::new();
+
+        while let Some(message) = history.front() {
+            let expired = oldest.is_some_and(|oldest| message.timestamp < oldest);
+            let excess = policy.max_count.is_some_and(|count| history.len() > count);
+            if !expired && !excess {
+                break;
+            }
+
+            if let Some(message) = history.pop_front() {
+                index.remove(message.seq, &message.content);
+                if let Some(attachment) = message.attachment {
+                    attachments.remove(&attachment.id);
+                    pruned.push(attachment);
+                }
+            }
+        }
+
+        pruned
+    }
+
+    /// Get the latest messages of the room history, oldest first.
+    pub fn history(&self, count: usize) -> Vec<ChatMessage> {
+        let history = self.history.read();
+        history
+            .iter()
+            .skip(history.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+
+    /// Search the room history, returning a page of hits, newest first, and
+    /// the total number of hits.
+    pub fn search(&self, query: &SearchQuery, page_size: usize) -> (Vec<SearchHit>, usize) {
+        let seqs = self.index.read().search(&query.text);
+        let history = self.history.read();
+        let hits: Vec<&ChatMessage> = seqs
+            .iter()
+            .rev()
+            .filter_map(|seq| {
+                let position = history.binary_search_by_key(seq, |message| message.seq);
+                history.get(position.ok()?)
+            })
+            .filter(|message| query.matches(message))
+            .collect();
+
+        let page = hits
+            .iter()
+            .skip(query.page.saturating_sub(1) * page_size)
+            .take(page_size)
+            .map(|message| SearchHit::of(message, &query.text))
+            .collect();
+
+        (page, hits.len())
+    }
+
+    /// Get a message from the room history followed by its replies.
+    pub fn thread(&self, message_id: &str) -> Result<Vec<ChatMessage>, RoomError> {
+        let history = self.history.read();
+        let parent = history
+            .iter()
+            .find(|message| message.id == message_id)
+            .ok_or_else(|| RoomError::MessageNotFound(message_id.to_string()))?;
+
+        let replies = history
+            .iter()
+            .filter(|message| matches!(&message.parent, Some(parent) if parent.id == message_id));
+
+        Ok(std::iter::once(parent).chain(replies).cloned().collect())
+    }
+
+    /// Edit a message in the room history; only its author may edit it.
+    pub fn edit_message(
+        &self,
+        message_id: &str,
+        user_id: &str,
+        content: &str,
+    ) -> Result<ChatMessage, RoomError> {
+        let mut history = self.history.write();
+        let message = history
+            .iter_mut()
+            .find(|message| message.id == message_id && !message.deleted)
+            .ok_or_else(|| RoomError::MessageNotFound(message_id.to_string()))?;
+
+        if message.author_id != user_id {
+            return Err(RoomError::NotPermitted(user_id.to_string()));
+        }
+
+        let mut index = self.index.write();
+        index.remove(message.seq, &message.content);
+        message.edit(content);
+        index.add(message.seq, &message.content);
+        Ok(message.clone())
+    }
+
+    /// Delete a message from the room history; its author and room
+    /// operators may delete it. Returns the file shared with the message, if
+    /// any, so that it can be removed.
+    pub fn delete_message(
+        &self,
+        message_id: &str,
+        user_id: &str,
+    ) -> Result<(ChatMessage, Option<Attachment>), RoomError> {
+        let mut history = self.history.write();
+        let message = history
+            .iter_mut()
+            .find(|message| message.id == message_id && !message.deleted)
+            .ok_or_else(|| RoomError::MessageNotFound(message_id.to_string()))?;
+
+        if message.author_id != user_id && !self.is_operator(user_id) {
+            return Err(RoomError::NotPermitted(user_id.to_string()));
+        }
+
+        let attachment = message
+            .attachment
+            .as_ref()
+            .and_then(|attachment| self.attachments.write().remove(&attachment.id));
+
+        self.index.write().remove(message.seq, &message.content);
+        message.delete();
+        Ok((message.clone(), attachment))
+    }
+
+    /// Get the usernames of room members mentioned in a message, in order of
+    /// first mention; trailing punctuation (e.g. `@alice,`) is ignored unless
+    /// it is part of a member's username.
+    fn members_mentioned(&self, content: &str) -> Vec<Username> {
+        let store = self.store.read();
+        let mut mentions: Vec<Username> = Vec::new();
+
+        for word in parse_mentions(content) {
+            let trimmed = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+            let is_member = |username: &str| store.values().any(|member| member == username);
+            let username = match is_member(word) {
+                true => Some(word),
+                false => Some(trimmed).filter(|trimmed| is_member(trimmed)),
+            };
+
+            if let Some(username) = username {
+                if !mentions.iter().any(|mention| mention == username) {
+                    mentions.push(username.to_string());
+                }
+            }
+        }
+
+        mentions
+    }
+
+    /// Get the sessions of the room members a message mentions, other than its author.
+    pub fn mentioned_sessions(&self, message: &ChatMessage) -> Vec<UserSessionId> {
+        self.store
+            .read()
+            .iter()
+            .filter(|(user_id, username)| {
+                **user_id != message.author_id && message.mentions.contains(username)
+            })
+            .map(|(user_id, _)| user_id.clone())
+            .collect()
+    }
+
+    /// Record that a member is typing; returns whether it should be broadcast,
+    /// which is at most once per rate limit window.
+    pub fn start_typing(&self, user_id: &str) -> bool {
+        let now = Instant::now();
+        let mut typing = self.typing.write();
+        let broadcast_at = match typing.get(user_id) {
+            Some((_, broadcast_at)) if broadcast_at.elapsed() < Self::TYPING_RATE_LIMIT => {
+                *broadcast_at
+            }
+            _ => now,
+        };
+
+        typing.insert(user_id.to_string(), (now, broadcast_at));
+        broadcast_at == now
+    }
+
+    /// Record that a member stopped typing; returns whether they were typing.
+    pub fn stop_typing(&self, user_id: &str) -> bool {
+        self.typing.write().remove(user_id).is_some()
+    }
+
+    /// Stop a member typing if they haven't been seen typing within the timeout;
+    /// returns whether they were stopped.
+    pub fn expire_typing(&self, user_id: &str, timeout: Duration) -> bool {
+        let mut typing = self.typing.write();
+        match typing.get(user_id) {
+            Some((seen_at, _)) if seen_at.elapsed() >= timeout => {
+                typing.remove(user_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the read marker of a username forward to a sequence number,
+    /// capped at the latest message; returns the new marker if it moved.
+    pub fn mark_read(&self, username: &str, seq: u64) -> Option<u64> {
+        let seq = seq.min(self.last_seq.load(Ordering::SeqCst));
+        let mut read_markers = self.read_markers.write();
+        let marker = read_markers.entry(username.to_string()).or_default();
+
+        if seq <= *marker {
+            return None;
+        }
+
+        *marker = seq;
+        Some(seq)
+    }
+
+    /// Number of messages from others after the read marker of a username,
+    /// not counting system messages, if anything was read under it.
+    pub fn unread_count(&self, username: &str) -> Option<usize> {
+        let marker = *self.read_markers.read().get(username)?;
+        let count = self
+            .history
+            .read()
+            .iter()
+            .filter(|message| message.seq > marker && !message.deleted)
+            .filter(|message| message.username != username)
+            .filter(|message| message.kind != MessageKind::System)
+            .count();
+
+        Some(count)
+    }
+
+    /// Check if a user is an operator of the room.
+    pub fn is_operator(&self, user_id: &str) -> bool {
+        self.operators.read().contains(user_id)
+    }
+
+    /// Get the sessions and usernames of the room members.
+    pub fn members(&self) -> Vec<(UserSessionId, Username)> {
+        self.store
+            .read()
+            .iter()
+            .map(|(user_id, username)| (user_id.clone(), username.clone()))
+            .collect()
     }
 
     // Get username of a user in a chatroom.
     pub fn get_username(&self, user_id: &str) -> Option<String> {
-        self.store.read().get(user_id).map(|s| s.clone())
+        self.store.read().get(user_id).cloned()
     }
 
     /// Check if chatroom is empty.
@@ -34,44 +499,326 @@ impl Room {
         self.store.read().is_empty()
     }
 
-    /// Add a client to the room.
+    /// Check if nothing would be lost by dropping the room: it has no
+    /// members, no history and no retention settings of its own.
+    pub fn is_disposable(&self) -> bool {
+        self.is_empty()
+            && self.history.read().is_empty()
+            && self.retention() == RoomRetention::default()
+    }
+
+    /// Add a client to the room; whoever creates the room becomes its operator.
     pub fn add(&self, user_id: &str, username: &str) -> Result<(), RoomError> {
-        if let Some(_) = self
-            .store
-            .write()
-            .insert(user_id.to_string(), username.to_string())
-        {
+        let mut store = self.store.write();
+        if store.contains_key(user_id) {
             return Err(RoomError::DuplicateSessionId(user_id.to_string()));
         }
 
+        if store.is_empty() {
+            self.operators.write().insert(user_id.to_string());
+        }
+
+        store.insert(user_id.to_string(), username.to_string());
         Ok(())
     }
 
     // Remove a user from the room.
     pub fn remove(&self, user_id: &str) {
         self.store.write().remove(user_id);
+        self.operators.write().remove(user_id);
+        self.typing.write().remove(user_id);
     }
 
     // Broadcast message to everyone in chat room excluding users specified.
     pub fn broadcast_to_excluding(
         &self,
-        message: &str,
+        message: &PlainTextMessage,
         excluding: &[&str],
+        users: &HashMap<UserSessionId, UserInfo>,
     ) -> Result<(), RoomError> {
-        let server = self.server.upgrade().ok_or(RoomError::NoServer)?;
+        let mut fanout = 0;
+        let mut failed = None;
 
-        self.store
-            .read()
-            .keys()
-            .into_iter()
-            .for_each(move |user_id| {
-                if !excluding.contains(&user_id.as_str()) {
-                    if let Some(address) = server.get_users().read().get(user_id) {
-                        let _ = address.0.do_send(PlainTextMessage(message.to_owned()));
-                    }
+        for user_id in self.store.read().keys() {
+            if excluding.contains(&user_id.as_str()) {
+                continue;
+            }
+
+            if let Some(user) = users.get(user_id) {
+                fanout += 1;
+
+                if let Err(err) = user.send(message.clone()) {
+                    metrics::DROPPED_MESSAGES.inc();
+                    failed = Some(err);
                 }
-            });
+            }
+        }
 
-        Ok(())
+        metrics::ROOM_FANOUT.observe(fanout as f64);
+
+        match failed {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room_with_messages(count: usize) -> Room {
+        let room = Room::new("rust");
+        for i in 0..count {
+            let content = format!("message {}", i);
+            room.new_message("alice-id", "alice", &content, MessageKind::Normal, None)
+                .unwrap();
+        }
+        room
+    }
+
+    fn attachment(id: &str) -> Attachment {
+        Attachment {
+            id: id.to_string(),
+            name: "notes.txt".to_string(),
+            size: 5,
+            content_type: "text/plain".to_string(),
+            url: format!("http://127.0.0.1:9100/attachments/{}", id),
+        }
+    }
+
+    #[test]
+    fn prune_keeps_the_latest_messages_up_to_max_count() {
+        let room = room_with_messages(5);
+        let policy = RetentionPolicy {
+            max_age_secs: None,
+            max_count: Some(2),
+        };
+
+        room.prune(policy, now_millis());
+
+        let seqs: Vec<u64> = room.history(10).iter().map(|m| m.seq).collect();
+        assert_eq!(seqs, vec![4, 5]);
+        assert_eq!(
+            room.index
+                .read()
+                .search("message")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+    }
+
+    #[test]
+    fn prune_drops_messages_older_than_max_age() {
+        let room = room_with_messages(3);
+        let policy = RetentionPolicy {
+            max_age_secs: Some(60),
+            max_count: None,
+        };
+
+        room.prune(policy, now_millis());
+        assert_eq!(room.history(10).len(), 3);
+
+        room.prune(policy, now_millis() + 61_000);
+        assert!(room.history(10).is_empty());
+    }
+
+    #[test]
+    fn room_policy_overrides_the_fallback() {
+        let room = room_with_messages(4);
+        room.set_retention(RoomRetention {
+            policy: RetentionPolicy {
+                max_age_secs: None,
+                max_count: Some(3),
+            },
+            legal_hold: false,
+        });
+
+        let fallback = RetentionPolicy {
+            max_age_secs: None,
+            max_count: Some(1),
+        };
+        room.prune(fallback, now_millis());
+        assert_eq!(room.history(10).len(), 3);
+    }
+
+    #[test]
+    fn legal_hold_suspends_pruning() {
+        let room = room_with_messages(3);
+        room.set_retention(RoomRetention {
+            policy: RetentionPolicy::default(),
+            legal_hold: true,
+        });
+
+        let policy = RetentionPolicy {
+            max_age_secs: Some(0),
+            max_count: Some(0),
+        };
+        assert!(room.prune(policy, now_millis() + 1_000).is_empty());
+        assert_eq!(room.history(10).len(), 3);
+    }
+
+    #[test]
+    fn prune_returns_the_attachments_of_pruned_messages() {
+        let room = Room::new("rust");
+        room.share_attachment("alice-id", "alice", attachment("a1"))
+            .unwrap();
+        room.new_message("alice-id", "alice", "hello", MessageKind::Normal, None)
+            .unwrap();
+
+        let policy = RetentionPolicy {
+            max_age_secs: None,
+            max_count: Some(1),
+        };
+        let pruned = room.prune(policy, now_millis());
+
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].id, "a1");
+        assert!(room.attachment("a1").is_err());
+    }
+
+    #[test]
+    fn delete_message_returns_its_attachment() {
+        let room = Room::new("rust");
+        let (shared, _) = room
+            .share_attachment("alice-id", "alice", attachment("a1"))
+            .unwrap();
+        let (text, _) = room
+            .new_message("alice-id", "alice", "hello", MessageKind::Normal, None)
+            .unwrap();
+
+        let (deleted, removed) = room.delete_message(&shared.id, "alice-id").unwrap();
+        assert!(deleted.attachment.is_none());
+        assert_eq!(
+            removed.map(|attachment| attachment.id),
+            Some("a1".to_string())
+        );
+        assert!(room.attachment("a1").is_err());
+
+        let (_, removed) = room.delete_message(&text.id, "alice-id").unwrap();
+        assert!(removed.is_none());
+    }
+
+    #[test]
+    fn new_message_returns_the_attachment_dropped_from_full_history() {
+        let room = Room::new("rust");
+        room.share_attachment("alice-id", "alice", attachment("a1"))
+            .unwrap();
+        for i in 1..Room::HISTORY_CAPACITY {
+            let content = format!("message {}", i);
+            let (_, evicted) = room
+                .new_message("alice-id", "alice", &content, MessageKind::Normal, None)
+                .unwrap();
+            assert!(evicted.is_none());
+        }
+
+        let (_, evicted) = room
+            .new_message("alice-id", "alice", "one more", MessageKind::Normal, None)
+            .unwrap();
+        assert_eq!(
+            evicted.map(|attachment| attachment.id),
+            Some("a1".to_string())
+        );
+        assert!(room.attachment("a1").is_err());
+    }
+
+    #[test]
+    fn import_never_moves_the_sequence_backwards() {
+        let room = room_with_messages(5);
+        let policy = RetentionPolicy {
+            max_age_secs: None,
+            max_count: Some(0),
+        };
+        room.prune(policy, now_millis());
+
+        let exported = room_with_messages(2).history(10);
+        assert_eq!(room.import(exported).unwrap(), 2);
+        let (message, _) = room
+            .new_message("alice-id", "alice", "after", MessageKind::Normal, None)
+            .unwrap();
+        assert_eq!(message.seq, 6);
+
+        let imported = Room::new("go");
+        imported.import(room.history(10)).unwrap();
+        let (message, _) = imported
+            .new_message("alice-id", "alice", "after", MessageKind::Normal, None)
+            .unwrap();
+        assert_eq!(message.seq, 7);
+    }
+
+    #[test]
+    fn search_returns_pages_of_hits_newest_first() {
+        let room = room_with_messages(5);
+        let query = SearchQuery {
+            text: "message".to_string(),
+            sender: None,
+            after: None,
+            before: None,
+            page: 2,
+        };
+
+        let (hits, total) = room.search(&query, 2);
+        let seqs: Vec<u64> = hits.iter().map(|hit| hit.seq).collect();
+        assert_eq!(total, 5);
+        assert_eq!(seqs, vec![3, 2]);
+    }
+
+    #[test]
+    fn search_follows_edits_and_deletions() {
+        let room = Room::new("rust");
+        let (first, _) = room
+            .new_message(
+                "alice-id",
+                "alice",
+                "deploy failed",
+                MessageKind::Normal,
+                None,
+            )
+            .unwrap();
+        let (second, _) = room
+            .new_message(
+                "alice-id",
+                "alice",
+                "deploy again",
+                MessageKind::Normal,
+                None,
+            )
+            .unwrap();
+        let query = |text: &str| SearchQuery {
+            text: text.to_string(),
+            sender: None,
+            after: None,
+            before: None,
+            page: 1,
+        };
+
+        room.edit_message(&first.id, "alice-id", "rollout failed")
+            .unwrap();
+        room.delete_message(&second.id, "alice-id").unwrap();
+
+        assert_eq!(room.search(&query("deploy"), 10).1, 0);
+        assert_eq!(room.search(&query("rollout"), 10).1, 1);
+    }
+
+    #[test]
+    fn room_is_disposable_once_empty_and_fully_pruned() {
+        let room = room_with_messages(1);
+        room.add("bob-id", "bob").unwrap();
+        room.remove("bob-id");
+        assert!(!room.is_disposable());
+
+        let policy = RetentionPolicy {
+            max_age_secs: None,
+            max_count: Some(0),
+        };
+        room.prune(policy, now_millis());
+        assert!(room.is_disposable());
+
+        room.set_retention(RoomRetention {
+            policy: RetentionPolicy::default(),
+            legal_hold: true,
+        });
+        assert!(!room.is_disposable());
     }
 }
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Limits on how much of a room's history is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Age in seconds after which messages are pruned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+
+    /// Number of most recent messages kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_count: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Take the limits this policy leaves unset from another one.
+    pub fn or(self, fallback: RetentionPolicy) -> Self {
+        Self {
+            max_age_secs: self.max_age_secs.or(fallback.max_age_secs),
+            max_count: self.max_count.or(fallback.max_count),
+        }
+    }
+}
+
+/// Retention settings of a room.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomRetention {
+    /// Limits overriding the server-wide retention policy.
+    #[serde(flatten)]
+    pub policy: RetentionPolicy,
+
+    /// Whether pruning the room's history is suspended.
+    #[serde(default)]
+    pub legal_hold: bool,
+}
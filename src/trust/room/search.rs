@@ -0,0 +1,207 @@
+use super::ChatMessage;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// Inverted index of the words in a room history, kept up to date as
+/// messages are added, edited, deleted and dropped from the history.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    /// Sequence numbers of the messages containing each word.
+    terms: HashMap<String, BTreeSet<u64>>,
+}
+
+impl SearchIndex {
+    /// Index the words of a message.
+    pub fn add(&mut self, seq: u64, content: &str) {
+        for term in terms(content) {
+            self.terms.entry(term).or_default().insert(seq);
+        }
+    }
+
+    /// Stop indexing the words of a message.
+    pub fn remove(&mut self, seq: u64, content: &str) {
+        for term in terms(content) {
+            if let Some(seqs) = self.terms.get_mut(&term) {
+                seqs.remove(&seq);
+                if seqs.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Get the sequence numbers of the messages containing every word of a query.
+    pub fn search(&self, query: &str) -> BTreeSet<u64> {
+        let mut matches: Option<BTreeSet<u64>> = None;
+
+        for term in terms(query) {
+            let seqs = match self.terms.get(&term) {
+                Some(seqs) => seqs,
+                None => return BTreeSet::new(),
+            };
+
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(seqs).copied().collect(),
+                None => seqs.clone(),
+            });
+        }
+
+        matches.unwrap_or_default()
+    }
+}
+
+/// A search of a room history.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    /// Words every hit must contain.
+    pub text: String,
+
+    /// Only messages sent by this user.
+    pub sender: Option<String>,
+
+    /// Only messages sent at or after this time, in milliseconds since the Unix epoch.
+    pub after: Option<u64>,
+
+    /// Only messages sent before this time, in milliseconds since the Unix epoch.
+    pub before: Option<u64>,
+
+    /// Page of hits to return, starting at 1.
+    pub page: usize,
+}
+
+impl SearchQuery {
+    /// Check if a message matches the sender and time filters.
+    pub fn matches(&self, message: &ChatMessage) -> bool {
+        self.sender
+            .as_ref()
+            .is_none_or(|sender| *sender == message.username)
+            && self.after.is_none_or(|after| message.timestamp >= after)
+            && self.before.is_none_or(|before| message.timestamp < before)
+    }
+}
+
+/// A message matching a search, with the words around the first match.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub seq: u64,
+    pub timestamp: u64,
+    pub username: String,
+    pub context: String,
+}
+
+impl SearchHit {
+    /// Number of words kept on each side of the first match.
+    const CONTEXT_WORDS: usize = 5;
+
+    pub fn of(message: &ChatMessage, query: &str) -> Self {
+        let query: BTreeSet<String> = terms(query).collect();
+        let words: Vec<&str> = message.content.split_whitespace().collect();
+        let first_match = words
+            .iter()
+            .position(|word| terms(word).any(|term| query.contains(&term)))
+            .unwrap_or_default();
+
+        let start = first_match.saturating_sub(Self::CONTEXT_WORDS);
+        let end = (first_match + Self::CONTEXT_WORDS + 1).min(words.len());
+        let mut context = words[start..end].join(" ");
+        if start > 0 {
+            context.insert(0, '…');
+        }
+
+        if end < words.len() {
+            context.push('…');
+        }
+
+        Self {
+            id: message.id.clone(),
+            seq: message.seq,
+            timestamp: message.timestamp,
+            username: message.username.clone(),
+            context,
+        }
+    }
+}
+
+/// Split text into the lowercase words it is indexed and searched by.
+pub fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::room::MessageKind;
+
+    fn seqs(index: &SearchIndex, query: &str) -> Vec<u64> {
+        index.search(query).into_iter().collect()
+    }
+
+    fn message(username: &str, timestamp: u64, content: &str) -> ChatMessage {
+        let mut message = ChatMessage::new(1, "rust", "id", username, content, MessageKind::Normal);
+        message.timestamp = timestamp;
+        message
+    }
+
+    #[test]
+    fn terms_are_lowercase_alphanumeric_words() {
+        let terms: Vec<String> = terms("Deploy FAILED, again: v1.2!").collect();
+        assert_eq!(terms, vec!["deploy", "failed", "again", "v1", "2"]);
+    }
+
+    #[test]
+    fn search_matches_messages_containing_every_word() {
+        let mut index = SearchIndex::default();
+        index.add(1, "the deploy failed");
+        index.add(2, "Deploy succeeded");
+        index.add(3, "lunch?");
+
+        assert_eq!(seqs(&index, "deploy"), vec![1, 2]);
+        assert_eq!(seqs(&index, "DEPLOY failed"), vec![1]);
+        assert!(seqs(&index, "deploy lunch").is_empty());
+        assert!(seqs(&index, "nothing").is_empty());
+        assert!(seqs(&index, "").is_empty());
+    }
+
+    #[test]
+    fn removed_messages_are_no_longer_found() {
+        let mut index = SearchIndex::default();
+        index.add(1, "deploy failed");
+        index.add(2, "deploy again");
+        index.remove(1, "deploy failed");
+
+        assert_eq!(seqs(&index, "deploy"), vec![2]);
+        assert!(seqs(&index, "failed").is_empty());
+        assert!(!index.terms.contains_key("failed"));
+    }
+
+    #[test]
+    fn query_filters_on_sender_and_time() {
+        let query = SearchQuery {
+            text: "deploy".to_string(),
+            sender: Some("alice".to_string()),
+            after: Some(100),
+            before: Some(200),
+            page: 1,
+        };
+
+        assert!(query.matches(&message("alice", 100, "deploy")));
+        assert!(!query.matches(&message("bob", 150, "deploy")));
+        assert!(!query.matches(&message("alice", 99, "deploy")));
+        assert!(!query.matches(&message("alice", 200, "deploy")));
+    }
+
+    #[test]
+    fn hit_context_keeps_words_around_the_first_match() {
+        let content =
+            "one two three four five six seven deploy eight nine ten eleven twelve thirteen";
+        let hit = SearchHit::of(&message("alice", 1, content), "Deploy");
+
+        assert_eq!(
+            hit.context,
+            "…three four five six seven deploy eight nine ten eleven twelve…"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::trust::room::RetentionPolicy;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+/// Chat server settings.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long a dropped session can be resumed before it is evicted.
+    pub resume_grace: Duration,
+
+    /// How long a typing indicator lasts without being refreshed.
+    pub typing_timeout: Duration,
+
+    /// Let room members know up to which message each of them has read.
+    pub read_receipts: bool,
+
+    /// Password which makes a session a server operator with `OPER`; nobody
+    /// can become one without it.
+    pub operator_password: Option<String>,
+
+    /// Message of the day, sent to every client right after it connects.
+    pub motd: Option<String>,
+
+    /// Retention limits of rooms which don't set their own.
+    pub retention: RetentionPolicy,
+
+    /// Incoming webhooks posting into rooms over HTTP.
+    pub incoming_hooks: Vec<IncomingHook>,
+
+    /// Number of messages each incoming webhook may post per minute.
+    pub hook_rate_limit: u32,
+
+    /// Number of messages kept for each evicted session; the oldest ones
+    /// are dropped beyond it.
+    pub offline_queue_capacity: usize,
+
+    /// Number of evicted sessions messages are kept for; the ones evicted
+    /// the longest ago are forgotten beyond it.
+    pub offline_queue_max_sessions: usize,
+
+    /// How long messages are kept for an evicted session.
+    pub offline_queue_ttl: Duration,
+
+    /// Directory files shared in rooms are stored in.
+    pub attachments_dir: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            resume_grace: Duration::from_secs(60),
+            typing_timeout: Duration::from_secs(5),
+            read_receipts: false,
+            operator_password: None,
+            motd: None,
+            retention: RetentionPolicy::default(),
+            incoming_hooks: Vec::new(),
+            hook_rate_limit: 30,
+            offline_queue_capacity: 100,
+            offline_queue_max_sessions: 1000,
+            offline_queue_ttl: Duration::from_secs(7 * 24 * 60 * 60),
+            attachments_dir: PathBuf::from("attachments"),
+        }
+    }
+}
+
+/// Incoming webhook, posting into a room under a display name; given as
+/// `{token}={room}={display name}`.
+#[derive(Debug, Clone)]
+pub struct IncomingHook {
+    /// Secret part of the webhook's URL.
+    pub token: String,
+    pub room: String,
+    pub username: String,
+}
+
+impl FromStr for IncomingHook {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.splitn(3, '=').map(str::trim);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(token), Some(room), Some(username))
+                if !token.is_empty() && !room.is_empty() && !username.is_empty() =>
+            {
+                Ok(Self {
+                    token: token.to_string(),
+                    room: room.to_string(),
+                    username: username.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "expected {{token}}={{room}}={{display name}}, got [{}]",
+                value
+            )),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::trust::{
+    room::{Attachment, ChatMessage, RoomRetention},
+    server::{TrustServer, TrustServerError},
+};
+use actix::{Context, Handler};
+use tracing::info;
+
+/// Get the whole history of a room.
+#[derive(actix::Message)]
+#[rtype(result = "Result<Vec<ChatMessage>, TrustServerError>")]
+pub struct ExportRoomContract {
+    pub room_name: String,
+}
+
+/// Fill the history of a room which has none yet.
+#[derive(actix::Message)]
+#[rtype(result = "Result<usize, TrustServerError>")]
+pub struct ImportRoomContract {
+    pub room_name: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Drop a room and its history.
+#[derive(actix::Message)]
+#[rtype(result = "Result<Vec<Attachment>, TrustServerError>")]
+pub struct DeleteRoomContract {
+    pub room_name: String,
+}
+
+/// Get the retention settings of a room.
+#[derive(actix::Message)]
+#[rtype(result = "Result<RoomRetention, TrustServerError>")]
+pub struct GetRetentionContract {
+    pub room_name: String,
+}
+
+/// Replace the retention settings of a room.
+#[derive(actix::Message)]
+#[rtype(result = "Result<RoomRetention, TrustServerError>")]
+pub struct SetRetentionContract {
+    pub room_name: String,
+    pub retention: RoomRetention,
+}
+
+/// Handler for Export Room message.
+impl Handler<ExportRoomContract> for TrustServer {
+    type Result = Result<Vec<ChatMessage>, TrustServerError>;
+
+    fn handle(&mut self, msg: ExportRoomContract, _: &mut Context<Self>) -> Self::Result {
+        let messages = self.export_room(&msg.room_name)?;
+        info!(room = %msg.room_name, messages = messages.len(), "Exported room history");
+        Ok(messages)
+    }
+}
+
+/// Handler for Import Room message.
+impl Handler<ImportRoomContract> for TrustServer {
+    type Result = Result<usize, TrustServerError>;
+
+    fn handle(&mut self, msg: ImportRoomContract, _: &mut Context<Self>) -> Self::Result {
+        let imported = self.import_room(&msg.room_name, msg.messages)?;
+        info!(room = %msg.room_name, messages = imported, "Imported room history");
+        Ok(imported)
+    }
+}
+
+/// Handler for Delete Room message.
+impl Handler<DeleteRoomContract> for TrustServer {
+    type Result = Result<Vec<Attachment>, TrustServerError>;
+
+    fn handle(&mut self, msg: DeleteRoomContract, _: &mut Context<Self>) -> Self::Result {
+        let attachments = self.delete_room(&msg.room_name)?;
+        info!(room = %msg.room_name, "Deleted room");
+        Ok(attachments)
+    }
+}
+
+/// Handler for Get Retention message.
+impl Handler<GetRetentionContract> for TrustServer {
+    type Result = Result<RoomRetention, TrustServerError>;
+
+    fn handle(&mut self, msg: GetRetentionContract, _: &mut Context<Self>) -> Self::Result {
+        self.room_retention(&msg.room_name)
+    }
+}
+
+/// Handler for Set Retention message.
+impl Handler<SetRetentionContract> for TrustServer {
+    type Result = Result<RoomRetention, TrustServerError>;
+
+    fn handle(&mut self, msg: SetRetentionContract, _: &mut Context<Self>) -> Self::Result {
+        self.set_room_retention(&msg.room_name, msg.retention)?;
+        info!(room = %msg.room_name, retention = ?msg.retention, "Updated room retention");
+        Ok(msg.retention)
+    }
+}
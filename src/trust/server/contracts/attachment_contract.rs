@@ -0,0 +1,47 @@
+use crate::trust::{
+    room::Attachment,
+    server::{ResumeToken, TrustServer, TrustServerError},
+};
+use actix::{Context, Handler};
+use tracing::info;
+
+/// Share an uploaded file in a room, on behalf of the session holding the
+/// resume token.
+#[derive(actix::Message)]
+#[rtype(result = "Result<(), TrustServerError>")]
+pub struct ShareAttachmentContract {
+    pub token: ResumeToken,
+    pub room_name: String,
+    pub attachment: Attachment,
+}
+
+/// Look up a file to download, on behalf of the session holding the resume
+/// token; only members of the room it was shared in may download it.
+#[derive(actix::Message)]
+#[rtype(result = "Result<Attachment, TrustServerError>")]
+pub struct FetchAttachmentContract {
+    pub token: ResumeToken,
+    pub attachment_id: String,
+}
+
+/// Handler for Share Attachment message.
+impl Handler<ShareAttachmentContract> for TrustServer {
+    type Result = Result<(), TrustServerError>;
+
+    fn handle(&mut self, msg: ShareAttachmentContract, _: &mut Context<Self>) -> Self::Result {
+        let user_id = self.get_user_by_token(&msg.token)?;
+        info!(user_id = %user_id, attachment_id = %msg.attachment.id, "User shared attachment");
+        self.share_attachment(&user_id, &msg.room_name, msg.attachment)?;
+        Ok(())
+    }
+}
+
+/// Handler for Fetch Attachment message.
+impl Handler<FetchAttachmentContract> for TrustServer {
+    type Result = Result<Attachment, TrustServerError>;
+
+    fn handle(&mut self, msg: FetchAttachmentContract, _: &mut Context<Self>) -> Self::Result {
+        let user_id = self.get_user_by_token(&msg.token)?;
+        Ok(self.fetch_attachment(&user_id, &msg.attachment_id)?)
+    }
+}
@@ -1,23 +1,36 @@
 use super::PlainTextMessage;
-use crate::log;
-use crate::trust::server::TrustServer;
 use crate::trust::server::TrustServerError;
+use crate::trust::server::{ResumeToken, TrustServer, UserSessionId};
 use actix::Recipient;
 use actix::{Context, Handler};
+use std::net::SocketAddr;
+use tracing::info;
 
 /// Connect a client message.
 #[derive(actix::Message)]
-#[rtype(result = "Result<String, TrustServerError>")]
+#[rtype(result = "Result<(UserSessionId, ResumeToken), TrustServerError>")]
 pub struct ConnectContract {
     pub addr: Recipient<PlainTextMessage>,
+    pub peer_addr: SocketAddr,
 }
 
 /// Handler for Connect message.
 impl Handler<ConnectContract> for TrustServer {
-    type Result = Result<String, TrustServerError>;
+    type Result = Result<(UserSessionId, ResumeToken), TrustServerError>;
 
     fn handle(&mut self, msg: ConnectContract, _: &mut Context<Self>) -> Self::Result {
-        log!("Someone just connected!!!");
-        self.handle_new_connection(msg.addr)
+        let (user_id, resume_token) = self.handle_new_connection(msg.addr)?;
+        info!(user_id = %user_id, peer_addr = %msg.peer_addr, "User connected");
+        self.plugins
+            .run(|plugin, handle| plugin.on_connect(handle, &user_id));
+
+        // Delivered once the session has handled this response, so it
+        // follows the resume token.
+        if let Some(motd) = &self.config.motd {
+            let motd = PlainTextMessage::Motd { text: motd.clone() };
+            self.message_user(&user_id, motd);
+        }
+
+        Ok((user_id, resume_token))
     }
 }
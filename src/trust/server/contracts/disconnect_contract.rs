@@ -1,8 +1,6 @@
-use crate::{
-    log,
-    trust::{response::user_left_message, server::TrustServer},
-};
-use actix::{Context, Handler};
+use crate::trust::server::TrustServer;
+use actix::{AsyncContext, Context, Handler};
+use tracing::info;
 
 /// Disconnect a client message.
 #[derive(actix::Message)]
@@ -12,15 +10,21 @@ pub struct DisconnectContract {
 }
 
 /// Handler for Disconnect message.
+///
+/// The session is only detached here; it is evicted (and the room told the
+/// user has left) if it isn't resumed within the grace window.
 impl Handler<DisconnectContract> for TrustServer {
     type Result = ();
 
-    fn handle(&mut self, msg: DisconnectContract, _: &mut Context<Self>) {
-        log!("User with id: [{}] disconnected", &msg.user_id);
+    fn handle(&mut self, msg: DisconnectContract, ctx: &mut Context<Self>) {
+        info!(user_id = %msg.user_id, "User disconnected");
+        self.plugins
+            .run(|plugin, handle| plugin.on_disconnect(handle, &msg.user_id));
 
-        if let Some(username) = self.get_username(&msg.user_id) {
-            self.evict_user_from_server(&msg.user_id);
-            self.broadcast_to_room_of_user(&msg.user_id, &user_left_message(&&username), &[]);
+        if self.detach_user(&msg.user_id) {
+            ctx.run_later(self.config.resume_grace, move |server, _| {
+                server.expire_detached_user(&msg.user_id);
+            });
         }
     }
 }
@@ -0,0 +1,25 @@
+use crate::trust::{
+    room::ChatMessage,
+    server::{TrustServer, TrustServerError},
+};
+use actix::{Context, Handler};
+use tracing::debug;
+
+/// Post a message to a room from an incoming webhook.
+#[derive(actix::Message)]
+#[rtype(result = "Result<ChatMessage, TrustServerError>")]
+pub struct IncomingHookContract {
+    pub token: String,
+    pub content: String,
+}
+
+/// Handler for Incoming Hook message.
+impl Handler<IncomingHookContract> for TrustServer {
+    type Result = Result<ChatMessage, TrustServerError>;
+
+    fn handle(&mut self, msg: IncomingHookContract, _: &mut Context<Self>) -> Self::Result {
+        let message = self.post_hook_message(&msg.token, &msg.content)?;
+        debug!(room = %message.room, username = %message.username, "Posted webhook message");
+        Ok(message)
+    }
+}
@@ -1,10 +1,156 @@
+mod admin_contract;
+mod attachment_contract;
 mod connect_contract;
 mod disconnect_contract;
+mod hook_contract;
+mod operator_contract;
+mod plugin_contract;
+mod presence_contract;
+mod resume_contract;
+mod retention_contract;
 mod room_contract;
+mod typing_contract;
 
-pub use self::{connect_contract::*, disconnect_contract::*, room_contract::*};
+pub use self::{
+    admin_contract::*, attachment_contract::*, connect_contract::*, disconnect_contract::*,
+    hook_contract::*, operator_contract::*, plugin_contract::*, presence_contract::*,
+    resume_contract::*, retention_contract::*, room_contract::*, typing_contract::*,
+};
+
+use super::RoomMember;
+use crate::trust::{
+    errors::ClientError,
+    room::{ChatMessage, ReactionChange, SearchHit},
+    user::CommandHelp,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
 
 /// Chat server sends this messages to session
-#[derive(actix::Message)]
+#[derive(actix::Message, Debug, Clone, Serialize)]
 #[rtype(result = "()")]
-pub struct PlainTextMessage(pub String);
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlainTextMessage {
+    /// Server notice, such as a user joining.
+    Text { text: String },
+
+    /// A command sent by the user failed.
+    Error(ClientError),
+
+    /// A tagged command sent by the user was handled.
+    Ok { tag: String },
+
+    /// A tagged command sent by the user failed.
+    Err {
+        tag: String,
+        #[serde(flatten)]
+        error: ClientError,
+    },
+
+    /// Message of the day, sent right after connecting.
+    Motd { text: String },
+
+    /// Notice from a server operator to every connected user.
+    ServerNotice { text: String },
+
+    /// Message sent by a user to a room.
+    Message(Box<ChatMessage>),
+
+    /// A message from the room history followed by its replies.
+    Thread {
+        room: String,
+        messages: Vec<ChatMessage>,
+    },
+
+    /// A message that mentions the recipient.
+    Mention(Box<ChatMessage>),
+
+    /// A message queued while the user's session was evicted, with when it
+    /// was sent; sent when their client comes back with its resume token.
+    Queued {
+        sent_at: u64,
+        message: Box<PlainTextMessage>,
+    },
+
+    /// A message in the room history was edited by its author.
+    MessageEdited(Box<ChatMessage>),
+
+    /// A message in the room history was deleted.
+    MessageDeleted {
+        room: String,
+        id: String,
+        seq: u64,
+        deleted_by: String,
+    },
+
+    /// A user added or removed a reaction to a message in the room history.
+    ReactionChanged(Box<ReactionChange>),
+
+    /// A user started or stopped typing; never stored in the room history.
+    Typing {
+        room: String,
+        username: String,
+        typing: bool,
+    },
+
+    /// A room member read the room up to a message.
+    ReadReceipt {
+        room: String,
+        username: String,
+        seq: u64,
+    },
+
+    /// Number of unread messages per room for the username a user joined a
+    /// room with, sent when they join.
+    Unread { rooms: BTreeMap<String, usize> },
+
+    /// Members of a room and whether they are at their keyboard.
+    Who {
+        room: String,
+        members: Vec<RoomMember>,
+    },
+
+    /// A page of messages matching a search of a room history, newest first.
+    SearchResults {
+        room: String,
+        query: String,
+        page: usize,
+        total: usize,
+        hits: Vec<SearchHit>,
+    },
+
+    /// The latest messages of the room history, oldest first.
+    History {
+        room: String,
+        messages: Vec<ChatMessage>,
+    },
+
+    /// Help about commands, in reply to `HELP`.
+    Help { commands: Vec<CommandHelp> },
+}
+
+impl PlainTextMessage {
+    /// Whether the message is only meaningful live, and not worth keeping
+    /// for a detached session.
+    pub fn is_ephemeral(&self) -> bool {
+        matches!(self, PlainTextMessage::Typing { .. })
+    }
+}
+
+impl From<String> for PlainTextMessage {
+    fn from(text: String) -> Self {
+        PlainTextMessage::Text { text }
+    }
+}
+
+impl From<ClientError> for PlainTextMessage {
+    fn from(error: ClientError) -> Self {
+        PlainTextMessage::Error(error)
+    }
+}
+
+impl From<ChatMessage> for PlainTextMessage {
+    fn from(message: ChatMessage) -> Self {
+        PlainTextMessage::Message(Box::new(message))
+    }
+}
@@ -0,0 +1,46 @@
+use crate::trust::{
+    response::oper_message,
+    server::{TrustServer, TrustServerError},
+};
+use actix::{Context, Handler};
+use tracing::{info, warn};
+
+/// Server operator commands.
+#[derive(actix::Message)]
+#[rtype(result = "Result<(), TrustServerError>")]
+pub enum OperatorContract {
+    /// Become a server operator with the operator password.
+    Oper { user_id: String, password: String },
+
+    /// Send a notice to every connected user.
+    Wallops { user_id: String, text: String },
+}
+
+/// Handler for Operator message.
+impl Handler<OperatorContract> for TrustServer {
+    type Result = Result<(), TrustServerError>;
+
+    fn handle(&mut self, msg: OperatorContract, _: &mut Context<Self>) -> Self::Result {
+        let (user_id, result) = match msg {
+            OperatorContract::Oper { user_id, password } => {
+                let result = self.make_server_operator(&user_id, &password);
+                if result.is_ok() {
+                    info!(user_id = %user_id, "User became a server operator");
+                    self.message_user(&user_id, oper_message());
+                }
+
+                (user_id, result)
+            }
+
+            OperatorContract::Wallops { user_id, text } => {
+                let result = self.broadcast_to_server(&user_id, &text);
+                (user_id, result)
+            }
+        };
+
+        result.map_err(|err| {
+            warn!(user_id = %user_id, error = %err, "Rejected server operator command");
+            err
+        })
+    }
+}
@@ -0,0 +1,34 @@
+use crate::trust::server::TrustServer;
+use actix::{Context, Handler};
+
+/// Message sent by a plugin through its handle.
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+pub enum PluginContract {
+    MessageUser {
+        user_id: String,
+        text: String,
+    },
+
+    Broadcast {
+        room_name: String,
+        username: String,
+        content: String,
+    },
+}
+
+/// Handler for Plugin message.
+impl Handler<PluginContract> for TrustServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: PluginContract, _: &mut Context<Self>) {
+        match msg {
+            PluginContract::MessageUser { user_id, text } => self.message_user(&user_id, text),
+            PluginContract::Broadcast {
+                room_name,
+                username,
+                content,
+            } => self.broadcast_plugin_message(&room_name, &username, &content),
+        }
+    }
+}
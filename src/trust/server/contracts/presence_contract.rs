@@ -0,0 +1,60 @@
+use crate::trust::{
+    response::{away_message, back_message},
+    server::{Presence, TrustServer, TrustServerError},
+};
+use actix::{Context, Handler};
+
+/// Change whether a user is at their keyboard.
+#[derive(actix::Message)]
+#[rtype(result = "Result<(), TrustServerError>")]
+pub enum PresenceContract {
+    /// The user marked themselves away.
+    Away {
+        user_id: String,
+        reason: Option<String>,
+    },
+
+    /// The user marked themselves back.
+    Back { user_id: String },
+
+    /// No activity from the client within the idle timeout.
+    Idle { user_id: String },
+
+    /// Activity from the client after it went idle.
+    Active { user_id: String },
+}
+
+/// Handler for Presence message.
+///
+/// Idle detection never overrides an explicit away status.
+impl Handler<PresenceContract> for TrustServer {
+    type Result = Result<(), TrustServerError>;
+
+    fn handle(&mut self, msg: PresenceContract, _: &mut Context<Self>) -> Self::Result {
+        match msg {
+            PresenceContract::Away { user_id, reason } => {
+                self.set_presence(&user_id, Presence::Away { reason });
+                self.message_user(&user_id, away_message());
+            }
+
+            PresenceContract::Back { user_id } => {
+                self.set_presence(&user_id, Presence::Online);
+                self.message_user(&user_id, back_message());
+            }
+
+            PresenceContract::Idle { user_id } => {
+                if self.get_presence(&user_id) == Some(Presence::Online) {
+                    self.set_presence(&user_id, Presence::Idle);
+                }
+            }
+
+            PresenceContract::Active { user_id } => {
+                if self.get_presence(&user_id) == Some(Presence::Idle) {
+                    self.set_presence(&user_id, Presence::Online);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
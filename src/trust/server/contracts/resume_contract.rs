@@ -0,0 +1,24 @@
+use super::PlainTextMessage;
+use crate::trust::server::{TrustServer, TrustServerError, UserSessionId};
+use actix::{Context, Handler, Recipient};
+use tracing::info;
+
+/// Resume a dropped session on a new connection.
+#[derive(actix::Message)]
+#[rtype(result = "Result<UserSessionId, TrustServerError>")]
+pub struct ResumeContract {
+    pub user_id: String,
+    pub token: String,
+    pub addr: Recipient<PlainTextMessage>,
+}
+
+/// Handler for Resume message.
+impl Handler<ResumeContract> for TrustServer {
+    type Result = Result<UserSessionId, TrustServerError>;
+
+    fn handle(&mut self, msg: ResumeContract, _: &mut Context<Self>) -> Self::Result {
+        let user_id = self.resume_session(&msg.user_id, &msg.token, msg.addr)?;
+        info!(user_id = %user_id, previous_user_id = %msg.user_id, "User resumed session");
+        Ok(user_id)
+    }
+}
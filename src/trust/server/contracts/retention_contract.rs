@@ -0,0 +1,16 @@
+use crate::trust::{room::Attachment, server::TrustServer};
+use actix::{Context, Handler, MessageResult};
+
+/// Prune the history of every room according to its retention policy.
+#[derive(actix::Message)]
+#[rtype(result = "Vec<Attachment>")]
+pub struct PruneHistoryContract;
+
+/// Handler for Prune History message.
+impl Handler<PruneHistoryContract> for TrustServer {
+    type Result = MessageResult<PruneHistoryContract>;
+
+    fn handle(&mut self, _: PruneHistoryContract, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.prune_history())
+    }
+}
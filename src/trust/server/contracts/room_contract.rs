@@ -1,60 +1,193 @@
 use crate::trust::{
-    response::{error_message, new_user_message, user_joined_message},
+    response::user_joined_message,
+    room::{MessageKind, RoomError, SearchQuery},
     server::{TrustServer, TrustServerError},
 };
 use actix::{Context, Handler};
+use tracing::debug;
 
 #[derive(actix::Message)]
-#[rtype(result = "Result<String, TrustServerError>")]
+#[rtype(result = "Result<(), TrustServerError>")]
 pub enum RoomContract {
     Join {
         user_id: String,
         username: String,
         room_name: String,
-        raw: String,
     },
     BroadcastMessage {
         user_id: String,
         content: String,
+        kind: MessageKind,
     },
+    EditMessage {
+        user_id: String,
+        message_id: String,
+        content: String,
+    },
+    DeleteMessage {
+        user_id: String,
+        message_id: String,
+    },
+    Reply {
+        user_id: String,
+        message_id: String,
+        content: String,
+    },
+    Thread {
+        user_id: String,
+        message_id: String,
+    },
+    React {
+        user_id: String,
+        message_id: String,
+        emoji: String,
+        add: bool,
+    },
+    History {
+        user_id: String,
+        count: Option<usize>,
+    },
+    MarkRead {
+        user_id: String,
+        room_name: String,
+        seq: u64,
+    },
+    Who {
+        user_id: String,
+    },
+    Search {
+        user_id: String,
+        room_name: String,
+        query: SearchQuery,
+    },
+}
+
+impl RoomContract {
+    /// Session of the user who issued the command.
+    pub fn user_id(&self) -> &str {
+        match self {
+            RoomContract::Join { user_id, .. }
+            | RoomContract::BroadcastMessage { user_id, .. }
+            | RoomContract::EditMessage { user_id, .. }
+            | RoomContract::DeleteMessage { user_id, .. }
+            | RoomContract::Reply { user_id, .. }
+            | RoomContract::Thread { user_id, .. }
+            | RoomContract::React { user_id, .. }
+            | RoomContract::History { user_id, .. }
+            | RoomContract::MarkRead { user_id, .. }
+            | RoomContract::Who { user_id }
+            | RoomContract::Search { user_id, .. } => user_id,
+        }
+    }
 }
 
 /// Handler for Chat Server Command message.
-impl<'e> Handler<RoomContract> for TrustServer {
-    type Result = Result<String, TrustServerError>;
+///
+/// Errors are returned to the user's session, which lets the client know.
+impl Handler<RoomContract> for TrustServer {
+    type Result = Result<(), TrustServerError>;
 
     fn handle(&mut self, command: RoomContract, _: &mut Context<Self>) -> Self::Result {
+        let user_id = command.user_id().to_string();
+
+        self.handle_room_command(command).map_err(|err| {
+            debug!(user_id = %user_id, error = %err, "Failed to handle room command");
+            err.into()
+        })
+    }
+}
+
+impl TrustServer {
+    fn handle_room_command(&self, command: RoomContract) -> Result<(), RoomError> {
         match command {
             RoomContract::Join {
                 user_id,
-                raw,
                 username,
                 room_name,
             } => match self.get_user_room(&user_id) {
-                Some(room_name) => {
-                    if let Some(username) = self.get_username(&user_id) {
-                        let message = new_user_message(&username, &raw);
-                        self.broadcast_to_room(&room_name, &&message, &[]);
-                    }
-                }
+                Some(room_name) => Err(RoomError::AlreadyInRoom(room_name)),
 
                 None => {
                     self.add_user_to_room(&room_name, &user_id, &username)?;
-                    self.broadcast_to_room(&room_name, &user_joined_message(&username), &[]);
+                    self.broadcast_system_message(
+                        &room_name,
+                        &username,
+                        user_joined_message(&username),
+                    );
+                    self.send_unread_counts(&user_id, &username);
+                    self.plugins.run(|plugin, handle| {
+                        plugin.on_join(handle, &room_name, &user_id, &username)
+                    });
+                    Ok(())
                 }
             },
 
-            RoomContract::BroadcastMessage { content, user_id } => {
-                match self.get_username(&user_id) {
-                    None => self.message_user(&user_id, &error_message()),
-                    Some(username) => {
-                        let message = new_user_message(&username, &content);
-                        self.broadcast_to_room_of_user(&user_id, &&message, &[])
-                    }
-                }
+            RoomContract::BroadcastMessage {
+                content,
+                user_id,
+                kind,
+            } => {
+                let username = self
+                    .get_username(&user_id)
+                    .ok_or_else(|| RoomError::NotInRoom(user_id.clone()))?;
+                self.broadcast_user_message(&user_id, &username, &content, kind, None)
             }
-        }
 
-        Ok("".to_string())
+            RoomContract::EditMessage {
+                user_id,
+                message_id,
+                content,
+            } => self.edit_message(&user_id, &message_id, &content),
+
+            RoomContract::DeleteMessage {
+                user_id,
+                message_id,
+            } => self.delete_message(&user_id, &message_id),
+
+            RoomContract::Reply {
+                user_id,
+                message_id,
+                content,
+            } => {
+                let username = self
+                    .get_username(&user_id)
+                    .ok_or_else(|| RoomError::NotInRoom(user_id.clone()))?;
+                self.broadcast_user_message(
+                    &user_id,
+                    &username,
+                    &content,
+                    MessageKind::Normal,
+                    Some(&message_id),
+                )
+            }
+
+            RoomContract::Thread {
+                user_id,
+                message_id,
+            } => self.send_thread(&user_id, &message_id),
+
+            RoomContract::React {
+                user_id,
+                message_id,
+                emoji,
+                add,
+            } => self.react_to_message(&user_id, &message_id, &emoji, add),
+
+            RoomContract::History { user_id, count } => self.send_history(&user_id, count),
+
+            RoomContract::MarkRead {
+                user_id,
+                room_name,
+                seq,
+            } => self.mark_read(&user_id, &room_name, seq),
+
+            RoomContract::Who { user_id } => self.send_who(&user_id),
+
+            RoomContract::Search {
+                user_id,
+                room_name,
+                query,
+            } => self.send_search_results(&user_id, &room_name, &query),
+        }
     }
 }
@@ -0,0 +1,33 @@
+use crate::trust::server::{TrustServer, TrustServerError};
+use actix::{AsyncContext, Context, Handler};
+
+/// A user started or stopped typing in their room.
+#[derive(actix::Message)]
+#[rtype(result = "Result<(), TrustServerError>")]
+pub struct TypingContract {
+    pub user_id: String,
+    pub typing: bool,
+}
+
+/// Handler for Typing message.
+///
+/// Typing indicators are never stored; one which isn't refreshed within the
+/// typing timeout is stopped on the user's behalf.
+impl Handler<TypingContract> for TrustServer {
+    type Result = Result<(), TrustServerError>;
+
+    fn handle(&mut self, msg: TypingContract, ctx: &mut Context<Self>) -> Self::Result {
+        if !msg.typing {
+            self.stop_typing(&msg.user_id);
+            return Ok(());
+        }
+
+        if self.start_typing(&msg.user_id) {
+            ctx.run_later(self.config.typing_timeout, move |server, _| {
+                server.expire_typing(&msg.user_id);
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,73 @@
-use crate::trust::room::RoomError;
+use crate::trust::{errors::ErrorCode, room::RoomError};
+use std::fmt;
 
 #[derive(Debug)]
 pub enum TrustServerError {
     RoomError(RoomError),
+    InvalidResumeToken,
+    SessionInUse(String),
+    SessionExpired,
+    InvalidOperatorPassword,
+    NotServerOperator(String),
+    RoomNotFound(String),
+    InvalidHookToken,
+    HookRateLimited(String),
+    RoomInUse(String),
+    LegalHold(String),
+    InvalidRoomName(String),
+}
+
+impl TrustServerError {
+    /// Code of the error sent to clients.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TrustServerError::RoomError(err) => err.code(),
+            TrustServerError::InvalidResumeToken => ErrorCode::InvalidResumeToken,
+            TrustServerError::SessionInUse(_) => ErrorCode::SessionInUse,
+            TrustServerError::SessionExpired => ErrorCode::SessionExpired,
+            TrustServerError::InvalidOperatorPassword => ErrorCode::InvalidOperatorPassword,
+            TrustServerError::NotServerOperator(_) => ErrorCode::NotServerOperator,
+            TrustServerError::RoomNotFound(_) => ErrorCode::RoomNotFound,
+            TrustServerError::InvalidHookToken => ErrorCode::InvalidHookToken,
+            TrustServerError::HookRateLimited(_) => ErrorCode::RateLimited,
+            TrustServerError::RoomInUse(_) => ErrorCode::RoomInUse,
+            TrustServerError::LegalHold(_) => ErrorCode::LegalHold,
+            TrustServerError::InvalidRoomName(_) => ErrorCode::InvalidRoomName,
+        }
+    }
+}
+
+impl fmt::Display for TrustServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustServerError::RoomError(err) => write!(f, "room error: {}", err),
+            TrustServerError::InvalidResumeToken => write!(f, "invalid or expired resume token"),
+            TrustServerError::SessionInUse(user_id) => {
+                write!(f, "session [{}] is still in use", user_id)
+            }
+            TrustServerError::SessionExpired => {
+                write!(f, "session expired; join a room again")
+            }
+            TrustServerError::InvalidOperatorPassword => write!(f, "invalid operator password"),
+            TrustServerError::NotServerOperator(user_id) => {
+                write!(f, "user [{}] is not a server operator", user_id)
+            }
+            TrustServerError::RoomNotFound(room_name) => {
+                write!(f, "room [{}] not found", room_name)
+            }
+            TrustServerError::InvalidHookToken => write!(f, "invalid webhook token"),
+            TrustServerError::HookRateLimited(username) => {
+                write!(f, "webhook [{}] is posting too many messages", username)
+            }
+            TrustServerError::RoomInUse(room_name) => {
+                write!(f, "room [{}] still has members", room_name)
+            }
+            TrustServerError::LegalHold(room_name) => {
+                write!(f, "room [{}] is on legal hold", room_name)
+            }
+            TrustServerError::InvalidRoomName(room_name) => {
+                write!(f, "invalid room name [{}]", room_name)
+            }
+        }
+    }
 }
@@ -1,16 +1,29 @@
+mod config;
 pub mod contracts;
 mod errors;
+mod offline_queue;
+mod presence;
+mod user_info;
 pub mod utils;
 
-use self::contracts::PlainTextMessage;
-pub use self::errors::*;
-use crate::{
-    log,
-    trust::room::{Room, RoomError},
+pub use self::{config::*, errors::*, offline_queue::*, presence::*, user_info::*};
+use self::{contracts::PlainTextMessage, utils::valid_room_name};
+use crate::trust::{
+    metrics,
+    plugins::{PendingMessage, Plugin, Plugins},
+    response::{session_resumed_message, user_left_message},
+    room::{
+        now_millis, parse_mentions, Attachment, ChatMessage, MessageKind, ReactionChange, Room,
+        RoomError, RoomRetention, SearchQuery,
+    },
 };
-use actix::{Actor, Context, Recipient};
+use actix::{Actor, AsyncContext, Context, Recipient};
 use parking_lot::RwLock;
-use std::{collections::HashMap, rc::Weak};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 /// User session identifier.
@@ -19,60 +32,277 @@ pub type UserSessionId = String;
 /// Chat room name.
 pub type RoomName = String;
 
-/// Chat user instance in the server.
-type UserInfo = (Recipient<PlainTextMessage>, Option<RoomName>);
+/// Resume token handed to a client to reclaim its session.
+pub type ResumeToken = String;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TrustServer {
+    config: ServerConfig,
     users: RwLock<HashMap<UserSessionId, UserInfo>>,
     rooms: RwLock<HashMap<RoomName, Room>>,
+    resume_tokens: RwLock<HashMap<ResumeToken, UserSessionId>>,
+    plugins: Plugins,
+
+    /// Start of the current rate limit window of each incoming webhook, and
+    /// the number of messages it posted since.
+    hook_usage: RwLock<HashMap<String, (Instant, u32)>>,
+
+    /// Messages for sessions whose resume grace window elapsed.
+    offline: RwLock<OfflineQueue>,
 }
 
 impl TrustServer {
+    /// Number of messages sent by `HISTORY` when no count is given.
+    const HISTORY_PAGE_SIZE: usize = 50;
+
+    /// Number of hits per page of search results.
+    const SEARCH_PAGE_SIZE: usize = 20;
+
+    /// Window incoming webhooks are rate limited over.
+    const HOOK_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Register a plugin, whose hooks run after those of the plugins
+    /// registered before it.
+    pub fn with_plugin(mut self, plugin: impl Plugin + 'static) -> Self {
+        self.plugins.register(Box::new(plugin));
+        self
+    }
+
     /// Handle a new client/user connection to the Chat server.
     fn handle_new_connection(
         &mut self,
         client: Recipient<PlainTextMessage>,
-    ) -> Result<String, TrustServerError> {
+    ) -> Result<(UserSessionId, ResumeToken), TrustServerError> {
         // TODO: Hopefully this scales to billions of users to have colliding uuids ;)
         let user_id = Uuid::new_v4().to_string();
-        self.users.write().insert(user_id.clone(), (client, None));
-        Ok(user_id)
-    }
+        let resume_token = Uuid::new_v4().to_simple().to_string();
+
+        self.users
+            .write()
+            .insert(user_id.clone(), UserInfo::new(client, resume_token.clone()));
+        self.resume_tokens
+            .write()
+            .insert(resume_token.clone(), user_id.clone());
+        metrics::CONNECTED_SESSIONS.inc();
 
-    /// Get all users in the chat server.
-    pub(crate) fn get_users(&self) -> &RwLock<HashMap<UserSessionId, UserInfo>> {
-        &self.users
+        Ok((user_id, resume_token))
     }
 
     /// Check if a user has already joined a room.
     fn get_user_room(&self, user_id: &str) -> Option<String> {
-        let lock = self.users.read();
-
-        if let Some((_, Some(room_name))) = lock.get(user_id) {
-            return Some(room_name.clone());
-        }
+        self.users.read().get(user_id)?.room().cloned()
+    }
 
-        None
+    /// Get the session a resume token belongs to.
+    fn get_user_by_token(&self, token: &str) -> Result<UserSessionId, TrustServerError> {
+        self.resume_tokens
+            .read()
+            .get(token)
+            .cloned()
+            .ok_or(TrustServerError::InvalidResumeToken)
     }
 
     /// Check if a user has already joined a room.
     fn get_username(&self, user_id: &str) -> Option<String> {
-        let lock = self.users.read();
+        let room_name = self.get_user_room(user_id)?;
+        self.rooms.read().get(&room_name)?.get_username(user_id)
+    }
+
+    /// Send a direct message to a user, queueing it if the user's session
+    /// was evicted.
+    fn message_user(&self, user_id: &str, message: impl Into<PlainTextMessage>) {
+        let message = message.into();
+        let delivered = match self.users.read().get(user_id) {
+            Some(user) => user.send(message).is_ok(),
+            None => return self.queue_offline_message(user_id, message),
+        };
+
+        if !delivered {
+            metrics::DROPPED_MESSAGES.inc();
+            debug!(user_id, "Dropped message to unreachable user");
+        }
+    }
 
-        if let Some((_, Some(room_name))) = lock.get(user_id) {
-            if let Some(room) = self.rooms.read().get(room_name) {
-                return room.get_username(user_id);
+    /// Queue a message for a user whose session was evicted, until its
+    /// client resumes it.
+    fn queue_offline_message(&self, user_id: &str, message: PlainTextMessage) {
+        let mut offline = self.offline.write();
+        let dropped = match offline.session_token(user_id) {
+            Some(token) => offline.push(&token, message, self.config.offline_queue_capacity),
+            None => true,
+        };
+
+        if dropped {
+            metrics::DROPPED_MESSAGES.inc();
+            debug!(user_id, "Dropped message to unknown or evicted user");
+        }
+    }
+
+    /// Get whether a user is at their keyboard.
+    fn get_presence(&self, user_id: &str) -> Option<Presence> {
+        Some(self.users.read().get(user_id)?.presence().clone())
+    }
+
+    /// Set whether a user is at their keyboard.
+    fn set_presence(&self, user_id: &str, presence: Presence) {
+        if let Some(user) = self.users.write().get_mut(user_id) {
+            user.set_presence(presence);
+        }
+    }
+
+    /// Make a user a server operator, if they know the operator password.
+    fn make_server_operator(&self, user_id: &str, password: &str) -> Result<(), TrustServerError> {
+        match &self.config.operator_password {
+            Some(expected) if expected == password => {}
+            _ => return Err(TrustServerError::InvalidOperatorPassword),
+        }
+
+        match self.users.write().get_mut(user_id) {
+            Some(user) => user.make_server_operator(),
+            None => return Err(TrustServerError::NotServerOperator(user_id.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Send a notice from a server operator to every connected user.
+    fn broadcast_to_server(&self, user_id: &str, text: &str) -> Result<(), TrustServerError> {
+        let users = self.users.read();
+        let is_server_operator = users.get(user_id).map(UserInfo::is_server_operator);
+        if is_server_operator != Some(true) {
+            return Err(TrustServerError::NotServerOperator(user_id.to_string()));
+        }
+
+        let notice = PlainTextMessage::ServerNotice {
+            text: text.to_string(),
+        };
+
+        for user in users.values() {
+            if user.send(notice.clone()).is_err() {
+                metrics::DROPPED_MESSAGES.inc();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Detach a user from its dropped connection, keeping the session
+    /// (and its room membership) around until the resume grace window ends.
+    fn detach_user(&self, user_id: &str) -> bool {
+        match self.users.write().get_mut(user_id) {
+            Some(user) if user.is_attached() => {
+                user.detach();
+                metrics::CONNECTED_SESSIONS.dec();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Evict a detached user once its resume grace window has elapsed,
+    /// letting the rest of its room know it has left.
+    fn expire_detached_user(&self, user_id: &str) {
+        let expired = match self
+            .users
+            .read()
+            .get(user_id)
+            .and_then(UserInfo::detached_at)
+        {
+            Some(detached_at) => detached_at.elapsed() >= self.config.resume_grace,
+            None => false,
+        };
+
+        if !expired {
+            return;
+        }
+
+        info!(user_id, "Resume grace window elapsed; evicting user");
+        let room_name = self.get_user_room(user_id);
+        let username = self.get_username(user_id);
+        let token = self
+            .users
+            .read()
+            .get(user_id)
+            .map(|user| user.resume_token().to_string());
+        self.evict_user_from_server(user_id);
+
+        if let (Some(room_name), Some(username)) = (room_name, username) {
+            if let Some(token) = token {
+                let mut offline = self.offline.write();
+                offline.expire(self.config.offline_queue_ttl);
+                offline.go_offline(
+                    &token,
+                    user_id,
+                    &room_name,
+                    &username,
+                    self.config.offline_queue_max_sessions,
+                );
             }
+
+            self.broadcast_system_message(&room_name, &username, user_left_message(&username));
+        }
+    }
+
+    /// Hand a detached session over to a new connection, replaying the
+    /// messages it missed. The new connection's own (fresh) session is dropped.
+    ///
+    /// If the session was already evicted, the new connection is sent the
+    /// messages queued for it instead, and keeps its own session.
+    fn resume_session(
+        &self,
+        current_user_id: &str,
+        token: &str,
+        client: Recipient<PlainTextMessage>,
+    ) -> Result<UserSessionId, TrustServerError> {
+        let user_id = self.resume_tokens.read().get(token).cloned();
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => return Err(self.send_queued_messages(current_user_id, token)),
+        };
+
+        if user_id == current_user_id || self.get_user_room(current_user_id).is_some() {
+            return Err(TrustServerError::SessionInUse(current_user_id.to_string()));
         }
 
-        None
+        let missed = match self.users.write().get_mut(&user_id) {
+            Some(user) if !user.is_attached() => user.attach(client),
+            Some(_) => return Err(TrustServerError::SessionInUse(user_id)),
+            None => return Err(TrustServerError::InvalidResumeToken),
+        };
+
+        metrics::CONNECTED_SESSIONS.inc();
+        self.evict_user_from_server(current_user_id);
+        self.message_user(&user_id, session_resumed_message());
+        missed
+            .into_iter()
+            .for_each(|message| self.message_user(&user_id, message));
+
+        Ok(user_id)
     }
 
-    /// Send a direct message to a user.
-    fn message_user(&self, user_id: &str, message: &str) {
-        if let Some((recipient, _)) = self.users.read().get(user_id) {
-            let _ = recipient.do_send(PlainTextMessage(message.to_string()));
+    /// Send a new session the messages queued for the evicted session a
+    /// resume token belonged to, returning the error to answer `RESUME` with.
+    fn send_queued_messages(&self, user_id: &str, token: &str) -> TrustServerError {
+        let queued = self
+            .offline
+            .write()
+            .take(token, self.config.offline_queue_ttl);
+
+        match queued {
+            Some(messages) => {
+                messages
+                    .into_iter()
+                    .for_each(|message| self.message_user(user_id, message));
+                TrustServerError::SessionExpired
+            }
+            None => TrustServerError::InvalidResumeToken,
         }
     }
 
@@ -88,76 +318,840 @@ impl TrustServer {
             return Err(RoomError::InvalidUserId(user_id.to_string()));
         }
 
-        {
-            unsafe {
-                let server_ptr = Weak::from_raw(self as *const Self);
-                self.rooms
-                    .write()
-                    .entry(room_name.to_string())
-                    .or_insert(Room::new(server_ptr))
-                    .add(user_id, username)?;
-            }
+        self.create_room(room_name);
+        if let Some(room) = self.rooms.read().get(room_name) {
+            room.add(user_id, username)?;
         }
 
         self.users
             .write()
             .get_mut(user_id)
             .ok_or(RoomError::InvalidUserId(user_id.to_string()))?
-            .1 = Some(room_name.to_string());
+            .set_room(room_name);
 
         Ok(())
     }
 
+    /// Create a room if it doesn't exist yet, whether a user joins it, its
+    /// history is imported or an incoming webhook posts to it. Rooms are only
+    /// dropped by the pruner or by an admin.
+    fn create_room(&self, room_name: &str) {
+        let mut rooms = self.rooms.write();
+        if !rooms.contains_key(room_name) {
+            rooms.insert(room_name.to_string(), Room::new(room_name));
+            metrics::ROOMS.set(rooms.len() as i64);
+        }
+    }
+
+    /// Drop a room without members and not on legal hold, with its history,
+    /// returning the files shared in it.
+    fn delete_room(&self, room_name: &str) -> Result<Vec<Attachment>, TrustServerError> {
+        let mut rooms = self.rooms.write();
+        let room = rooms
+            .get(room_name)
+            .ok_or_else(|| TrustServerError::RoomNotFound(room_name.to_string()))?;
+
+        if !room.is_empty() {
+            return Err(TrustServerError::RoomInUse(room_name.to_string()));
+        }
+
+        if room.retention().legal_hold {
+            return Err(TrustServerError::LegalHold(room_name.to_string()));
+        }
+
+        let attachments = room.attachments();
+        rooms.remove(room_name);
+        metrics::ROOMS.set(rooms.len() as i64);
+        Ok(attachments)
+    }
+
+    /// Get the whole history of a room, oldest first.
+    fn export_room(&self, room_name: &str) -> Result<Vec<ChatMessage>, TrustServerError> {
+        match self.rooms.read().get(room_name) {
+            Some(room) => Ok(room.history(usize::MAX)),
+            None => Err(TrustServerError::RoomNotFound(room_name.to_string())),
+        }
+    }
+
+    /// Fill the history of a room which has none yet, creating the room if
+    /// it doesn't exist.
+    fn import_room(
+        &self,
+        room_name: &str,
+        messages: Vec<ChatMessage>,
+    ) -> Result<usize, TrustServerError> {
+        if !valid_room_name(Some(room_name)) {
+            return Err(TrustServerError::InvalidRoomName(room_name.to_string()));
+        }
+
+        self.create_room(room_name);
+        match self.rooms.read().get(room_name) {
+            Some(room) => Ok(room.import(messages)?),
+            None => Err(TrustServerError::RoomNotFound(room_name.to_string())),
+        }
+    }
+
+    /// Get the retention settings of a room.
+    fn room_retention(&self, room_name: &str) -> Result<RoomRetention, TrustServerError> {
+        match self.rooms.read().get(room_name) {
+            Some(room) => Ok(room.retention()),
+            None => Err(TrustServerError::RoomNotFound(room_name.to_string())),
+        }
+    }
+
+    /// Replace the retention settings of a room.
+    fn set_room_retention(
+        &self,
+        room_name: &str,
+        retention: RoomRetention,
+    ) -> Result<(), TrustServerError> {
+        let rooms = self.rooms.read();
+        let room = rooms
+            .get(room_name)
+            .ok_or_else(|| TrustServerError::RoomNotFound(room_name.to_string()))?;
+
+        room.set_retention(retention);
+        Ok(())
+    }
+
+    /// Prune the history of every room according to its retention policy,
+    /// returning the files shared with the pruned messages.
+    ///
+    /// Rooms outlive their members; this is where the ones left with nothing
+    /// worth keeping are dropped.
+    fn prune_history(&self) -> Vec<Attachment> {
+        let now = now_millis();
+        let mut rooms = self.rooms.write();
+        let pruned = rooms
+            .values()
+            .flat_map(|room| room.prune(self.config.retention, now))
+            .collect();
+
+        rooms.retain(|_, room| !room.is_disposable());
+        metrics::ROOMS.set(rooms.len() as i64);
+        pruned
+    }
+
     /// Evict user completely from the server by deleting every record
     /// of the user (including socket connection).
     fn evict_user_from_server(&self, user_id: &str) {
         self.remove_user_active_room(user_id);
-        self.users.write().remove(user_id);
+
+        if let Some(user) = self.users.write().remove(user_id) {
+            self.resume_tokens.write().remove(user.resume_token());
+
+            if user.is_attached() {
+                metrics::CONNECTED_SESSIONS.dec();
+            }
+        }
     }
 
-    /// Remove user from their currently active room.
+    /// Remove user from their currently active room. The room, with its
+    /// history, is kept even once its last member left.
     fn remove_user_active_room(&self, user_id: &str) {
-        if let Some((_, Some(room_name))) = self.users.read().get(user_id) {
-            if let Some(room) = self.rooms.read().get(room_name) {
+        if let Some(room_name) = self.get_user_room(user_id) {
+            if let Some(room) = self.rooms.read().get(&room_name) {
+                if let Some(username) = room.get_username(user_id) {
+                    self.plugins.run(|plugin, handle| {
+                        plugin.on_leave(handle, &room_name, user_id, &username)
+                    });
+                }
+
                 room.remove(user_id);
+            }
+        }
+    }
+
+    /// Broadcast a new chat message of the given kind from a user to the room
+    /// they have joined, optionally as a reply to an earlier message.
+    fn broadcast_user_message(
+        &self,
+        user_id: &str,
+        username: &str,
+        content: &str,
+        kind: MessageKind,
+        parent_id: Option<&str>,
+    ) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        self.broadcast_message(&room_name, user_id, username, content, kind, parent_id)?;
+        Ok(())
+    }
+
+    /// Post a message from an incoming webhook to its room, creating the
+    /// room if it doesn't exist.
+    fn post_hook_message(
+        &self,
+        token: &str,
+        content: &str,
+    ) -> Result<ChatMessage, TrustServerError> {
+        let hook = self
+            .config
+            .incoming_hooks
+            .iter()
+            .find(|hook| hook.token == token)
+            .ok_or(TrustServerError::InvalidHookToken)?;
+
+        {
+            let mut usage = self.hook_usage.write();
+            let (window_start, posted) = usage
+                .entry(hook.token.clone())
+                .or_insert_with(|| (Instant::now(), 0));
+
+            if window_start.elapsed() >= Self::HOOK_RATE_WINDOW {
+                *window_start = Instant::now();
+                *posted = 0;
+            }
+
+            if *posted >= self.config.hook_rate_limit {
+                return Err(TrustServerError::HookRateLimited(hook.username.clone()));
+            }
+
+            *posted += 1;
+        }
+
+        self.create_room(&hook.room);
+        let message = self.broadcast_message(
+            &hook.room,
+            "",
+            &hook.username,
+            content,
+            MessageKind::Normal,
+            None,
+        )?;
+
+        Ok(message)
+    }
+
+    /// Broadcast a new chat message to a room once plugins have let it
+    /// through, keeping it in the room history. Members it mentions are also
+    /// sent a separate mention event.
+    fn broadcast_message(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        username: &str,
+        content: &str,
+        kind: MessageKind,
+        parent_id: Option<&str>,
+    ) -> Result<ChatMessage, RoomError> {
+        let pending = self.before_broadcast(room_name, user_id, username, content, kind)?;
+
+        let (message, evicted, mentioned) = match self.rooms.read().get(room_name) {
+            Some(room) => {
+                let (message, evicted) = room.new_message(
+                    user_id,
+                    &pending.username,
+                    &pending.content,
+                    pending.kind,
+                    parent_id,
+                )?;
+                let mentioned = room.mentioned_sessions(&message);
+                (message, evicted, mentioned)
+            }
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        if let Some(attachment) = evicted {
+            self.remove_attachment_file(attachment);
+        }
+
+        self.broadcast_to_room(room_name, message.clone(), &[]);
+        self.plugins
+            .run(|plugin, handle| plugin.on_message(handle, &message));
+        for mentioned_id in mentioned {
+            self.message_user(
+                &mentioned_id,
+                PlainTextMessage::Mention(Box::new(message.clone())),
+            );
+        }
+        self.queue_offline_mentions(room_name, &message);
+
+        Ok(message)
+    }
+
+    /// Run a message on its way to a room through the plugins'
+    /// `before_broadcast` hooks, returning it as they let it through.
+    fn before_broadcast(
+        &self,
+        room_name: &str,
+        user_id: &str,
+        username: &str,
+        content: &str,
+        kind: MessageKind,
+    ) -> Result<PendingMessage, RoomError> {
+        let mut pending = PendingMessage {
+            room: room_name.to_string(),
+            user_id: user_id.to_string(),
+            username: username.to_string(),
+            content: content.to_string(),
+            kind,
+        };
+        self.plugins
+            .before_broadcast(&mut pending)
+            .map_err(RoomError::MessageRejected)?;
+
+        Ok(pending)
+    }
+
+    /// Broadcast a server generated message about a user (e.g. them joining)
+    /// to a room, keeping it in the room history. Nobody authors it, so only
+    /// room operators can delete it.
+    fn broadcast_system_message(&self, room_name: &str, username: &str, text: String) {
+        let message = match self.rooms.read().get(room_name) {
+            Some(room) => room.new_message("", username, &text, MessageKind::System, None),
+            None => return,
+        };
+
+        match message {
+            Ok((message, evicted)) => {
+                if let Some(attachment) = evicted {
+                    self.remove_attachment_file(attachment);
+                }
+                self.broadcast_to_room(room_name, message, &[]);
+            }
+            Err(err) => warn!(room = room_name, error = %err, "Failed to add system message"),
+        }
+    }
+
+    /// Broadcast a notice from a plugin to a room, keeping it in the room
+    /// history.
+    fn broadcast_plugin_message(&self, room_name: &str, username: &str, content: &str) {
+        let message = match self.rooms.read().get(room_name) {
+            Some(room) => room.new_message("", username, content, MessageKind::Notice, None),
+            None => return debug!(room = room_name, "Dropped plugin message to unknown room"),
+        };
 
-                if room.is_empty() {
-                    self.rooms.write().remove(room_name);
+        match message {
+            Ok((message, evicted)) => {
+                if let Some(attachment) = evicted {
+                    self.remove_attachment_file(attachment);
                 }
+                self.broadcast_to_room(room_name, message, &[]);
             }
+            Err(err) => warn!(room = room_name, error = %err, "Failed to add plugin message"),
+        }
+    }
+
+    /// Let a user's room know they are typing, at most once per rate limit
+    /// window; returns whether the user is in a room.
+    fn start_typing(&self, user_id: &str) -> bool {
+        let room_name = match self.get_user_room(user_id) {
+            Some(room_name) => room_name,
+            None => return false,
+        };
+
+        let started = match self.rooms.read().get(&room_name) {
+            Some(room) => room.start_typing(user_id),
+            None => return false,
+        };
+
+        if started {
+            self.broadcast_typing(&room_name, user_id, true);
         }
+
+        true
     }
 
-    /// Broadcast message to the room of a user.
-    fn broadcast_to_room_of_user(&self, user_id: &str, message: &str, exclude_user_ids: &[&str]) {
+    /// Let a user's room know they stopped typing.
+    fn stop_typing(&self, user_id: &str) {
         if let Some(room_name) = self.get_user_room(user_id) {
-            self.broadcast_to_room(&room_name, message, exclude_user_ids);
+            let stopped = match self.rooms.read().get(&room_name) {
+                Some(room) => room.stop_typing(user_id),
+                None => false,
+            };
+
+            if stopped {
+                self.broadcast_typing(&room_name, user_id, false);
+            }
         }
     }
 
-    /// Broadcast a message to all members of a room.
-    fn broadcast_to_room(&self, room_name: &str, message: &str, exclude_user_ids: &[&str]) {
-        if let Some(room) = self.rooms.read().get(room_name) {
-            if let Err(err) = room.broadcast_to_excluding(message, exclude_user_ids) {
-                log!(
-                    "Failed to send message to room: [{}]; error: [{:?}]",
-                    room_name,
-                    err
-                )
+    /// Stop a user typing once they haven't refreshed it within the typing timeout.
+    fn expire_typing(&self, user_id: &str) {
+        if let Some(room_name) = self.get_user_room(user_id) {
+            let expired = match self.rooms.read().get(&room_name) {
+                Some(room) => room.expire_typing(user_id, self.config.typing_timeout),
+                None => false,
+            };
+
+            if expired {
+                self.broadcast_typing(&room_name, user_id, false);
             }
         }
     }
-}
 
-impl Default for TrustServer {
-    fn default() -> Self {
-        TrustServer {
-            rooms: RwLock::default(),
-            users: RwLock::default(),
+    /// Broadcast a typing indicator of a user to the rest of their room.
+    fn broadcast_typing(&self, room_name: &str, user_id: &str, typing: bool) {
+        let event = PlainTextMessage::Typing {
+            room: room_name.to_string(),
+            username: self.get_username(user_id).unwrap_or_default(),
+            typing,
+        };
+        self.broadcast_to_room(room_name, event, &[user_id]);
+    }
+
+    /// Move the read marker of a user's username in their room forward,
+    /// optionally letting the rest of the room know.
+    fn mark_read(&self, user_id: &str, room_name: &str, seq: u64) -> Result<(), RoomError> {
+        let username = self
+            .get_username(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        if self.get_user_room(user_id).as_deref() != Some(room_name) {
+            return Err(RoomError::NotInRoom(user_id.to_string()));
+        }
+
+        let marked = match self.rooms.read().get(room_name) {
+            Some(room) => room.mark_read(&username, seq),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        if let (Some(seq), true) = (marked, self.config.read_receipts) {
+            let receipt = PlainTextMessage::ReadReceipt {
+                room: room_name.to_string(),
+                username,
+                seq,
+            };
+            self.broadcast_to_room(room_name, receipt, &[user_id]);
+        }
+
+        Ok(())
+    }
+
+    /// Send a user the number of unread messages in every room messages were
+    /// marked read in under their username.
+    fn send_unread_counts(&self, user_id: &str, username: &str) {
+        let rooms: BTreeMap<String, usize> = self
+            .rooms
+            .read()
+            .iter()
+            .filter_map(|(room_name, room)| Some((room_name.clone(), room.unread_count(username)?)))
+            .collect();
+
+        if !rooms.is_empty() {
+            self.message_user(user_id, PlainTextMessage::Unread { rooms });
+        }
+    }
+
+    /// Queue a message for the sessions evicted from the room whose username
+    /// it mentions; as with room members, trailing punctuation is ignored
+    /// unless part of the username.
+    fn queue_offline_mentions(&self, room_name: &str, message: &ChatMessage) {
+        let mut offline = self.offline.write();
+        let mut tokens: Vec<ResumeToken> = Vec::new();
+        for word in parse_mentions(&message.content) {
+            let mut mentioned = offline.room_tokens(room_name, word);
+            if mentioned.is_empty() {
+                let username = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+                mentioned = offline.room_tokens(room_name, username);
+            }
+
+            for token in mentioned {
+                if !tokens.contains(&token) {
+                    tokens.push(token);
+                }
+            }
+        }
+
+        for token in tokens {
+            let mention = PlainTextMessage::Mention(Box::new(message.clone()));
+            if offline.push(&token, mention, self.config.offline_queue_capacity) {
+                metrics::DROPPED_MESSAGES.inc();
+                debug!("Dropped message to unknown or evicted user");
+            }
+        }
+    }
+
+    /// Send a user the members of their room and whether they are at their keyboard.
+    fn send_who(&self, user_id: &str) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let members = match self.rooms.read().get(&room_name) {
+            Some(room) => room.members(),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        let users = self.users.read();
+        let mut members: Vec<RoomMember> = members
+            .into_iter()
+            .map(|(member_id, username)| RoomMember {
+                username,
+                presence: users
+                    .get(&member_id)
+                    .map(|user| user.presence().clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        drop(users);
+
+        members.sort_by(|a, b| a.username.cmp(&b.username));
+        self.message_user(
+            user_id,
+            PlainTextMessage::Who {
+                room: room_name,
+                members,
+            },
+        );
+        Ok(())
+    }
+
+    /// Share a file in the user's room, letting the room know. Its name is
+    /// the content of the message, so it goes through the plugins'
+    /// `before_broadcast` hooks like any other message.
+    fn share_attachment(
+        &self,
+        user_id: &str,
+        room_name: &str,
+        mut attachment: Attachment,
+    ) -> Result<(), RoomError> {
+        let username = self
+            .get_username(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        if self.get_user_room(user_id).as_deref() != Some(room_name) {
+            return Err(RoomError::NotInRoom(user_id.to_string()));
+        }
+
+        let pending = self.before_broadcast(
+            room_name,
+            user_id,
+            &username,
+            &attachment.name,
+            MessageKind::Normal,
+        )?;
+        attachment.name = pending.content;
+
+        let (message, evicted) = match self.rooms.read().get(room_name) {
+            Some(room) => room.share_attachment(user_id, &username, attachment)?,
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        if let Some(attachment) = evicted {
+            self.remove_attachment_file(attachment);
+        }
+
+        self.broadcast_to_room(room_name, message.clone(), &[]);
+        self.plugins
+            .run(|plugin, handle| plugin.on_message(handle, &message));
+        Ok(())
+    }
+
+    /// Get a file shared in the user's room.
+    fn fetch_attachment(
+        &self,
+        user_id: &str,
+        attachment_id: &str,
+    ) -> Result<Attachment, RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        match self.rooms.read().get(&room_name) {
+            Some(room) => room.attachment(attachment_id),
+            None => Err(RoomError::NotInRoom(user_id.to_string())),
+        }
+    }
+
+    /// Search the history of the user's room, sending them a page of hits.
+    fn send_search_results(
+        &self,
+        user_id: &str,
+        room_name: &str,
+        query: &SearchQuery,
+    ) -> Result<(), RoomError> {
+        if self.get_user_room(user_id).as_deref() != Some(room_name) {
+            return Err(RoomError::NotInRoom(user_id.to_string()));
+        }
+
+        let (hits, total) = match self.rooms.read().get(room_name) {
+            Some(room) => room.search(query, Self::SEARCH_PAGE_SIZE),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        let results = PlainTextMessage::SearchResults {
+            room: room_name.to_string(),
+            query: query.text.clone(),
+            page: query.page,
+            total,
+            hits,
+        };
+        self.message_user(user_id, results);
+        Ok(())
+    }
+
+    /// Send a user a message from their room followed by its replies.
+    fn send_thread(&self, user_id: &str, message_id: &str) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let messages = match self.rooms.read().get(&room_name) {
+            Some(room) => room.thread(message_id)?,
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        self.message_user(
+            user_id,
+            PlainTextMessage::Thread {
+                room: room_name,
+                messages,
+            },
+        );
+        Ok(())
+    }
+
+    /// Send a user the latest messages of their room history.
+    fn send_history(&self, user_id: &str, count: Option<usize>) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let count = count.unwrap_or(Self::HISTORY_PAGE_SIZE);
+        let messages = match self.rooms.read().get(&room_name) {
+            Some(room) => room.history(count),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        self.message_user(
+            user_id,
+            PlainTextMessage::History {
+                room: room_name,
+                messages,
+            },
+        );
+        Ok(())
+    }
+
+    /// Add or remove a user's reaction to a message in their room, and let
+    /// the room know.
+    fn react_to_message(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        emoji: &str,
+        add: bool,
+    ) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let (message, username) = match self.rooms.read().get(&room_name) {
+            Some(room) => (
+                room.react(message_id, user_id, emoji, add)?,
+                room.get_username(user_id).unwrap_or_default(),
+            ),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        let event = PlainTextMessage::ReactionChanged(Box::new(ReactionChange {
+            room: room_name.clone(),
+            reactions: message.reaction_counts(),
+            id: message.id,
+            seq: message.seq,
+            username,
+            emoji: emoji.to_string(),
+            added: add,
+        }));
+        self.broadcast_to_room(&room_name, event, &[]);
+        Ok(())
+    }
+
+    /// Edit a message the user sent to their room, and let the room know.
+    /// The new content goes through the plugins' `before_broadcast` hooks.
+    fn edit_message(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        content: &str,
+    ) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let username = self.get_username(user_id).unwrap_or_default();
+        let pending =
+            self.before_broadcast(&room_name, user_id, &username, content, MessageKind::Normal)?;
+
+        let message = match self.rooms.read().get(&room_name) {
+            Some(room) => room.edit_message(message_id, user_id, &pending.content)?,
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        self.broadcast_to_room(
+            &room_name,
+            PlainTextMessage::MessageEdited(Box::new(message)),
+            &[],
+        );
+        Ok(())
+    }
+
+    /// Delete a message from the user's room, and let the room know.
+    fn delete_message(&self, user_id: &str, message_id: &str) -> Result<(), RoomError> {
+        let room_name = self
+            .get_user_room(user_id)
+            .ok_or_else(|| RoomError::NotInRoom(user_id.to_string()))?;
+
+        let ((message, attachment), deleted_by) = match self.rooms.read().get(&room_name) {
+            Some(room) => (
+                room.delete_message(message_id, user_id)?,
+                room.get_username(user_id).unwrap_or_default(),
+            ),
+            None => return Err(RoomError::NotInRoom(user_id.to_string())),
+        };
+
+        if let Some(attachment) = attachment {
+            self.remove_attachment_file(attachment);
+        }
+
+        let event = PlainTextMessage::MessageDeleted {
+            room: room_name.clone(),
+            id: message.id,
+            seq: message.seq,
+            deleted_by,
+        };
+        self.broadcast_to_room(&room_name, event, &[]);
+        Ok(())
+    }
+
+    /// Remove the stored file of an attachment nobody can download anymore.
+    fn remove_attachment_file(&self, attachment: Attachment) {
+        let path = self.config.attachments_dir.join(&attachment.id);
+
+        actix::spawn(async move {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                warn!(path = %path.display(), error = %err, "Failed to remove attachment");
+            }
+        });
+    }
+
+    /// Broadcast a message to all members of a room.
+    fn broadcast_to_room(
+        &self,
+        room_name: &str,
+        message: impl Into<PlainTextMessage>,
+        exclude_user_ids: &[&str],
+    ) {
+        if let Some(room) = self.rooms.read().get(room_name) {
+            let users = self.users.read();
+            let message = message.into();
+            if let Err(err) = room.broadcast_to_excluding(&message, exclude_user_ids, &users) {
+                warn!(room = room_name, error = %err, "Failed to send message to room")
+            }
         }
     }
 }
 
 impl Actor for TrustServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.plugins.start(ctx.address());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::{
+        plugins::WordFilterPlugin,
+        server::contracts::{ConnectContract, RoomContract},
+    };
+    use actix::{Handler, MessageResult, System};
+
+    /// Session keeping the messages the chat server sends it.
+    #[derive(Default)]
+    struct Inbox(Vec<PlainTextMessage>);
+
+    /// Take the messages an inbox received so far.
+    #[derive(actix::Message)]
+    #[rtype(result = "Vec<PlainTextMessage>")]
+    struct Take;
+
+    impl Actor for Inbox {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<PlainTextMessage> for Inbox {
+        type Result = ();
+
+        fn handle(&mut self, msg: PlainTextMessage, _: &mut Context<Self>) {
+            self.0.push(msg);
+        }
+    }
+
+    impl Handler<Take> for Inbox {
+        type Result = MessageResult<Take>;
+
+        fn handle(&mut self, _: Take, _: &mut Context<Self>) -> Self::Result {
+            MessageResult(std::mem::take(&mut self.0))
+        }
+    }
+
+    #[test]
+    fn word_filter_masks_edited_messages() {
+        System::new().block_on(async {
+            let server = TrustServer::default()
+                .with_plugin(WordFilterPlugin::new(&["darn".to_string()]))
+                .start();
+            let inbox = Inbox::default().start();
+
+            let connect = ConnectContract {
+                addr: inbox.clone().recipient(),
+                peer_addr: ([127, 0, 0, 1], 0).into(),
+            };
+            let (user_id, _) = server.send(connect).await.unwrap().unwrap();
+
+            let join = RoomContract::Join {
+                user_id: user_id.clone(),
+                username: "alice".to_string(),
+                room_name: "rust".to_string(),
+            };
+            server.send(join).await.unwrap().unwrap();
+
+            let send = RoomContract::BroadcastMessage {
+                user_id: user_id.clone(),
+                content: "hello".to_string(),
+                kind: MessageKind::Normal,
+            };
+            server.send(send).await.unwrap().unwrap();
+
+            // Messages to the inbox were sent before the server answered, so
+            // they are ahead of `Take` in its mailbox.
+            let message_id = inbox
+                .send(Take)
+                .await
+                .unwrap()
+                .into_iter()
+                .find_map(|message| match message {
+                    PlainTextMessage::Message(message) if message.author_id == user_id => {
+                        Some(message.id)
+                    }
+                    _ => None,
+                })
+                .unwrap();
+
+            let edit = RoomContract::EditMessage {
+                user_id,
+                message_id,
+                content: "well darn".to_string(),
+            };
+            server.send(edit).await.unwrap().unwrap();
+
+            let edited = inbox
+                .send(Take)
+                .await
+                .unwrap()
+                .into_iter()
+                .find_map(|message| match message {
+                    PlainTextMessage::MessageEdited(message) => Some(message.content),
+                    _ => None,
+                });
+            assert_eq!(edited.as_deref(), Some("well ****"));
+        });
+    }
 }
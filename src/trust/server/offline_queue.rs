@@ -0,0 +1,223 @@
+use super::{contracts::PlainTextMessage, ResumeToken, RoomName, UserSessionId};
+use crate::trust::room::now_millis;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Messages kept for sessions whose resume grace window elapsed, until their
+/// client comes back with the session's resume token.
+///
+/// There are no accounts, so the resume token is the only thing a client can
+/// prove it owns: messages are queued per evicted session, and only a
+/// `RESUME` with its token gets them. Mentions are queued for the room and
+/// username the session had joined with.
+#[derive(Debug, Default)]
+pub struct OfflineQueue {
+    sessions: HashMap<ResumeToken, OfflineSession>,
+}
+
+#[derive(Debug)]
+struct OfflineSession {
+    user_id: UserSessionId,
+    room: RoomName,
+    username: String,
+
+    /// When the session was evicted.
+    since: Instant,
+
+    /// Queued messages, oldest first, with when they were queued.
+    messages: VecDeque<(Instant, PlainTextMessage)>,
+}
+
+impl OfflineQueue {
+    /// Start queueing messages for an evicted session, forgetting the one
+    /// evicted the longest ago once `max_sessions` are queued for.
+    pub fn go_offline(
+        &mut self,
+        token: &str,
+        user_id: &str,
+        room: &str,
+        username: &str,
+        max_sessions: usize,
+    ) {
+        if max_sessions == 0 {
+            return;
+        }
+
+        while self.sessions.len() >= max_sessions {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.since)
+                .map(|(token, _)| token.clone());
+
+            match oldest {
+                Some(oldest) => self.sessions.remove(&oldest),
+                None => break,
+            };
+        }
+
+        self.sessions.insert(
+            token.to_string(),
+            OfflineSession {
+                user_id: user_id.to_string(),
+                room: room.to_string(),
+                username: username.to_string(),
+                since: Instant::now(),
+                messages: VecDeque::new(),
+            },
+        );
+    }
+
+    /// Get the resume token of an evicted session.
+    pub fn session_token(&self, user_id: &str) -> Option<ResumeToken> {
+        self.sessions
+            .iter()
+            .find(|(_, session)| session.user_id == user_id)
+            .map(|(token, _)| token.clone())
+    }
+
+    /// Get the resume tokens of the sessions evicted from a room they had
+    /// joined with a username.
+    pub fn room_tokens(&self, room: &str, username: &str) -> Vec<ResumeToken> {
+        self.sessions
+            .iter()
+            .filter(|(_, session)| session.room == room && session.username == username)
+            .map(|(token, _)| token.clone())
+            .collect()
+    }
+
+    /// Queue a message for an evicted session, dropping its oldest queued
+    /// message beyond `capacity`; returns whether one was dropped.
+    pub fn push(&mut self, token: &str, message: PlainTextMessage, capacity: usize) -> bool {
+        let session = match self.sessions.get_mut(token) {
+            Some(session) => session,
+            None => return false,
+        };
+
+        let queued = PlainTextMessage::Queued {
+            sent_at: now_millis(),
+            message: Box::new(message),
+        };
+        session.messages.push_back((Instant::now(), queued));
+        if session.messages.len() > capacity {
+            session.messages.pop_front();
+            return true;
+        }
+
+        false
+    }
+
+    /// Stop queueing messages for an evicted session whose client came back,
+    /// taking the ones queued within `ttl`, oldest first, each with when it
+    /// was sent. Returns `None` if nothing was queued for the token.
+    pub fn take(&mut self, token: &str, ttl: Duration) -> Option<Vec<PlainTextMessage>> {
+        self.expire(ttl);
+        let session = self.sessions.remove(token)?;
+        Some(
+            session
+                .messages
+                .into_iter()
+                .map(|(_, message)| message)
+                .collect(),
+        )
+    }
+
+    /// Drop the messages queued longer than `ttl` ago, and forget the
+    /// sessions evicted longer than that ago with nothing left queued.
+    pub fn expire(&mut self, ttl: Duration) {
+        self.sessions.retain(|_, session| {
+            session
+                .messages
+                .retain(|(queued_at, _)| queued_at.elapsed() < ttl);
+            !session.messages.is_empty() || session.since.elapsed() < ttl
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn message(text: &str) -> PlainTextMessage {
+        PlainTextMessage::Text {
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(messages: Option<Vec<PlainTextMessage>>) -> Vec<String> {
+        messages
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|queued| match queued {
+                PlainTextMessage::Queued { sent_at, message } => match *message {
+                    PlainTextMessage::Text { text } if sent_at > 0 => Some(text),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sessions_are_found_by_id_or_by_room_and_username() {
+        let mut queue = OfflineQueue::default();
+        queue.go_offline("token-1", "alice-id", "rust", "alice", 10);
+
+        assert_eq!(queue.session_token("alice-id").as_deref(), Some("token-1"));
+        assert_eq!(queue.session_token("bob-id"), None);
+        assert_eq!(queue.room_tokens("rust", "alice"), vec!["token-1"]);
+        assert!(queue.room_tokens("go", "alice").is_empty());
+    }
+
+    #[test]
+    fn push_drops_the_oldest_message_beyond_capacity() {
+        let mut queue = OfflineQueue::default();
+        queue.go_offline("token-1", "alice-id", "rust", "alice", 10);
+
+        assert!(!queue.push("token-1", message("one"), 2));
+        assert!(!queue.push("token-1", message("two"), 2));
+        assert!(queue.push("token-1", message("three"), 2));
+        assert!(!queue.push("token-2", message("four"), 2));
+        assert_eq!(texts(queue.take("token-1", TTL)), vec!["two", "three"]);
+    }
+
+    #[test]
+    fn go_offline_forgets_the_oldest_session_beyond_max_sessions() {
+        let mut queue = OfflineQueue::default();
+        queue.go_offline("token-1", "alice-id", "rust", "alice", 2);
+        queue.go_offline("token-2", "bob-id", "rust", "bob", 2);
+        queue.go_offline("token-3", "carol-id", "rust", "carol", 2);
+
+        assert_eq!(queue.session_token("alice-id"), None);
+        assert!(queue.session_token("bob-id").is_some());
+        assert!(queue.session_token("carol-id").is_some());
+    }
+
+    #[test]
+    fn take_only_answers_the_session_token_once() {
+        let mut queue = OfflineQueue::default();
+        queue.go_offline("token-1", "alice-id", "rust", "alice", 10);
+        queue.push("token-1", message("one"), 10);
+
+        assert!(queue.take("alice-id", TTL).is_none());
+        assert_eq!(texts(queue.take("token-1", TTL)), vec!["one"]);
+        assert!(queue.take("token-1", TTL).is_none());
+    }
+
+    #[test]
+    fn expire_drops_old_messages_and_forgets_idle_sessions() {
+        let mut queue = OfflineQueue::default();
+        queue.go_offline("token-1", "alice-id", "rust", "alice", 10);
+        queue.push("token-1", message("one"), 10);
+
+        queue.expire(TTL);
+        assert!(queue.session_token("alice-id").is_some());
+
+        queue.expire(Duration::from_secs(0));
+        assert!(queue.session_token("alice-id").is_none());
+    }
+}
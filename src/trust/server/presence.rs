@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+/// Whether a user is at their keyboard.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(tag = "presence", rename_all = "snake_case")]
+pub enum Presence {
+    #[default]
+    Online,
+
+    /// No activity from the client within the idle timeout.
+    Idle,
+
+    /// Set by the user with `AWAY`, until they are `BACK`.
+    Away {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+/// A member of a room, as listed by `WHO`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomMember {
+    pub username: String,
+
+    #[serde(flatten)]
+    pub presence: Presence,
+}
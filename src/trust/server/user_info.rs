@@ -0,0 +1,110 @@
+use super::{contracts::PlainTextMessage, Presence, RoomName};
+use actix::{clock::Instant, prelude::SendError, Recipient};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// Chat user instance in the server.
+#[derive(Debug)]
+pub struct UserInfo {
+    /// Address of the connected session; `None` while the session is detached.
+    recipient: Option<Recipient<PlainTextMessage>>,
+    room: Option<RoomName>,
+    resume_token: String,
+    detached_at: Option<Instant>,
+    missed: Mutex<VecDeque<PlainTextMessage>>,
+    presence: Presence,
+    server_operator: bool,
+}
+
+impl UserInfo {
+    /// Maximum number of messages kept for a detached session.
+    const MISSED_MESSAGES_CAPACITY: usize = 100;
+
+    pub fn new(recipient: Recipient<PlainTextMessage>, resume_token: String) -> Self {
+        Self {
+            recipient: Some(recipient),
+            room: None,
+            resume_token,
+            detached_at: None,
+            missed: Mutex::default(),
+            presence: Presence::default(),
+            server_operator: false,
+        }
+    }
+
+    /// Get the room the user has joined.
+    pub fn room(&self) -> Option<&RoomName> {
+        self.room.as_ref()
+    }
+
+    /// Set the room the user has joined.
+    pub fn set_room(&mut self, room_name: &str) {
+        self.room = Some(room_name.to_string());
+    }
+
+    /// Whether the user is at their keyboard.
+    pub fn presence(&self) -> &Presence {
+        &self.presence
+    }
+
+    /// Set whether the user is at their keyboard.
+    pub fn set_presence(&mut self, presence: Presence) {
+        self.presence = presence;
+    }
+
+    /// Check if the user is a server operator.
+    pub fn is_server_operator(&self) -> bool {
+        self.server_operator
+    }
+
+    /// Make the user a server operator.
+    pub fn make_server_operator(&mut self) {
+        self.server_operator = true;
+    }
+
+    /// Token the client can use to resume this session after a network drop.
+    pub fn resume_token(&self) -> &str {
+        &self.resume_token
+    }
+
+    /// Check if the session currently has a live connection.
+    pub fn is_attached(&self) -> bool {
+        self.recipient.is_some()
+    }
+
+    /// When the session lost its connection, if it is detached.
+    pub fn detached_at(&self) -> Option<Instant> {
+        self.detached_at
+    }
+
+    /// Detach the session from its connection, buffering messages until resumed.
+    pub fn detach(&mut self) {
+        self.recipient = None;
+        self.detached_at = Some(Instant::now());
+    }
+
+    /// Attach a new connection to the session, returning the messages it missed.
+    pub fn attach(&mut self, recipient: Recipient<PlainTextMessage>) -> Vec<PlainTextMessage> {
+        self.recipient = Some(recipient);
+        self.detached_at = None;
+        self.missed.lock().drain(..).collect()
+    }
+
+    /// Send a message to the user, or buffer it if the session is detached.
+    /// Ephemeral messages are dropped rather than buffered.
+    pub fn send(&self, message: PlainTextMessage) -> Result<(), SendError<PlainTextMessage>> {
+        match &self.recipient {
+            Some(recipient) => recipient.do_send(message),
+            None if message.is_ephemeral() => Ok(()),
+            None => {
+                let mut missed = self.missed.lock();
+                if missed.len() == Self::MISSED_MESSAGES_CAPACITY {
+                    missed.pop_front();
+                }
+
+                missed.push_back(message);
+                Ok(())
+            }
+        }
+    }
+}
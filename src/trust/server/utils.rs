@@ -1,15 +1,103 @@
+use unicode_segmentation::UnicodeSegmentation;
+
 /// Is the username args specified valid?
 pub fn valid_username(username_arg: Option<&str>) -> bool {
-    match username_arg {
-        Some(username) if username.chars().count() > 0 && username.chars().count() < 20 => true,
-        _ => false,
-    }
+    matches!(
+        username_arg,
+        Some(username) if username.chars().count() > 0 && username.chars().count() < 20
+    )
 }
 
 /// Is the chatroom args specified valid?
 pub fn valid_room_name(room_name_arg: Option<&str>) -> bool {
-    match room_name_arg {
-        Some(room_name) if room_name.chars().count() > 0 && room_name.chars().count() < 20 => true,
+    matches!(
+        room_name_arg,
+        Some(room_name) if room_name.chars().count() > 0 && room_name.chars().count() < 20
+    )
+}
+
+/// Is the reaction args specified a single emoji: one grapheme, made of a
+/// pictographic character (possibly with modifiers or joined with others),
+/// a symbol in emoji presentation or a keycap (e.g. `1️⃣`)?
+pub fn valid_emoji(emoji_arg: Option<&str>) -> bool {
+    let emoji = match emoji_arg {
+        Some(emoji) if emoji.graphemes(true).count() == 1 => emoji,
+        _ => return false,
+    };
+
+    let mut chars = emoji.chars();
+    match (chars.next(), chars.next()) {
+        (Some('0'..='9' | '#' | '*'), _) => emoji.ends_with('\u{20E3}'),
+        (Some(first), _) if is_pictographic(first) => true,
+        (Some(first), Some('\u{FE0F}')) => !first.is_alphanumeric() && !first.is_whitespace(),
         _ => false,
     }
 }
+
+/// Is the character one emoji are drawn from? Covers the Unicode
+/// `Extended_Pictographic` characters in use.
+fn is_pictographic(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x00A9
+            | 0x00AE
+            | 0x203C
+            | 0x2049
+            | 0x2122
+            | 0x2139
+            | 0x2194..=0x2199
+            | 0x21A9..=0x21AA
+            | 0x231A..=0x231B
+            | 0x2328
+            | 0x2388
+            | 0x23CF
+            | 0x23E9..=0x23F3
+            | 0x23F8..=0x23FA
+            | 0x24C2
+            | 0x25AA..=0x25AB
+            | 0x25B6
+            | 0x25C0
+            | 0x25FB..=0x25FE
+            | 0x2600..=0x27BF
+            | 0x2934..=0x2935
+            | 0x2B05..=0x2B07
+            | 0x2B1B..=0x2B1C
+            | 0x2B50
+            | 0x2B55
+            | 0x3030
+            | 0x303D
+            | 0x3297
+            | 0x3299
+            | 0x1F000..=0x1FAFF
+    )
+}
+
+/// Is the request tag specified short, and made of letters, digits, `-`, `_` and `.`?
+pub fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 64
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_emoji_accepts_single_emoji() {
+        for emoji in ["👍", "👍🏽", "❤️", "🇳🇬", "👩‍💻", "1️⃣", "#️⃣", "*⃣", "©️"]
+        {
+            assert!(valid_emoji(Some(emoji)), "{}", emoji);
+        }
+    }
+
+    #[test]
+    fn valid_emoji_rejects_text() {
+        for text in ["1", "#", "a", "é", "中", "👍👍", "+1", " ", "a\u{FE0F}"] {
+            assert!(!valid_emoji(Some(text)), "{}", text);
+        }
+        assert!(!valid_emoji(None));
+    }
+}
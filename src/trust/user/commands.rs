@@ -0,0 +1,1044 @@
+use super::contracts::UserContract;
+use crate::trust::{
+    errors::ErrorCode,
+    room::SearchQuery,
+    server::utils::{valid_emoji, valid_room_name, valid_tag, valid_username},
+};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// Who may run a command. Room membership and operator status are checked
+/// by the chat server when it handles the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Anyone,
+    RoomMember,
+    ServerOperator,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Anyone => write!(f, "anyone"),
+            Permission::RoomMember => write!(f, "room members"),
+            Permission::ServerOperator => write!(f, "server operators"),
+        }
+    }
+}
+
+/// Error in a line sent by a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    Unknown {
+        name: String,
+        /// Name of the closest command, if the name looks like a typo.
+        suggestion: Option<&'static str>,
+    },
+    Usage(String),
+    InvalidArgument(&'static str),
+    InvalidRoomName,
+    InvalidUsername,
+    EmptyMessage,
+    InvalidReaction,
+    InvalidSearch,
+    InvalidSearchFilter(String),
+}
+
+impl CommandError {
+    /// Code of the error sent to clients.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CommandError::Empty => ErrorCode::EmptyCommand,
+            CommandError::Unknown { .. } => ErrorCode::UnknownCommand,
+            CommandError::Usage(_) | CommandError::InvalidArgument(_) => {
+                ErrorCode::InvalidArguments
+            }
+            CommandError::InvalidRoomName => ErrorCode::InvalidRoomName,
+            CommandError::InvalidUsername => ErrorCode::InvalidUsername,
+            CommandError::EmptyMessage => ErrorCode::EmptyMessage,
+            CommandError::InvalidReaction => ErrorCode::InvalidReaction,
+            CommandError::InvalidSearch | CommandError::InvalidSearchFilter(_) => {
+                ErrorCode::InvalidSearch
+            }
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "Command cannot be empty"),
+            CommandError::Unknown {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "Unknown command [{}], did you mean {}?",
+                name,
+                suggestion.to_ascii_uppercase()
+            ),
+            CommandError::Unknown { name, .. } => {
+                write!(f, "Unknown command [{}], send HELP for a list", name)
+            }
+            CommandError::Usage(usage) => write!(f, "Usage: {}", usage),
+            CommandError::InvalidArgument(what) => write!(f, "Invalid {}", what),
+            CommandError::InvalidRoomName => write!(f, "Invalid room name"),
+            CommandError::InvalidUsername => write!(f, "Invalid username"),
+            CommandError::EmptyMessage => write!(f, "Message content cannot be empty"),
+            CommandError::InvalidReaction => write!(f, "Invalid reaction"),
+            CommandError::InvalidSearch => write!(f, "Invalid search command specified"),
+            CommandError::InvalidSearchFilter(value) => {
+                write!(f, "Invalid search filter [{}]", value)
+            }
+        }
+    }
+}
+
+/// How many words an argument takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// A single word which must be given.
+    Required,
+
+    /// A single word which may be left out.
+    Optional,
+
+    /// The rest of the line, which must not be empty.
+    Text,
+
+    /// The rest of the line, which may be empty.
+    OptionalText,
+}
+
+/// Argument of a command.
+#[derive(Debug, Clone, Copy)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+}
+
+impl Arg {
+    const fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind }
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ArgKind::Required | ArgKind::Text => write!(f, "{{{}}}", self.name),
+            ArgKind::Optional | ArgKind::OptionalText => write!(f, "[{}]", self.name),
+        }
+    }
+}
+
+/// Arguments given to a command, checked against its schema.
+struct Args<'a> {
+    values: Vec<Option<&'a str>>,
+}
+
+impl<'a> Args<'a> {
+    /// Get an argument by its position in the command's schema.
+    fn get(&self, position: usize) -> Option<&'a str> {
+        self.values.get(position).copied().flatten()
+    }
+
+    /// Get an argument the schema requires, which is always given.
+    fn required(&self, position: usize) -> &'a str {
+        self.get(position).unwrap_or_default()
+    }
+}
+
+/// A command clients can send, with everything needed to parse it and to
+/// explain it with `HELP`.
+pub struct Command {
+    /// Name of the command, in lowercase; commands are case insensitive
+    /// with the `/` prefix, and must be in capitals without it.
+    pub name: &'static str,
+
+    /// Other names of the command, only recognised with the `/` prefix.
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    pub permission: Permission,
+    pub help: &'static str,
+
+    /// Whether the command is part of the session protocol (e.g. heartbeats),
+    /// and so recognised without a `/` prefix in every mode.
+    protocol: bool,
+
+    parse: fn(&Args) -> Result<UserContract, CommandError>,
+}
+
+impl Command {
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+
+    /// Usage line of the command, e.g. `REPLY {message_id} {text}`.
+    pub fn usage(&self) -> String {
+        std::iter::once(self.name.to_ascii_uppercase())
+            .chain(self.args.iter().map(Arg::to_string))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Check the arguments given after the command name against its schema.
+    fn args<'a>(&self, line: &'a str) -> Result<Args<'a>, CommandError> {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        let mut values = Vec::with_capacity(self.args.len());
+
+        for (position, arg) in self.args.iter().enumerate() {
+            let value = match arg.kind {
+                ArgKind::Required | ArgKind::Optional => words.get(position).copied(),
+                ArgKind::Text | ArgKind::OptionalText => remainder(line, position),
+            };
+
+            let missing = value.is_none();
+            if missing && matches!(arg.kind, ArgKind::Required | ArgKind::Text) {
+                return Err(CommandError::Usage(self.usage()));
+            }
+
+            values.push(value);
+        }
+
+        let takes_text = self
+            .args
+            .iter()
+            .any(|arg| matches!(arg.kind, ArgKind::Text | ArgKind::OptionalText));
+        if !takes_text && words.len() > self.args.len() {
+            return Err(CommandError::Usage(self.usage()));
+        }
+
+        Ok(Args { values })
+    }
+}
+
+/// Help about a command, as sent by `HELP`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandHelp {
+    pub name: String,
+
+    /// Aliases, with the `/` prefix they need.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    pub usage: String,
+    pub permission: Permission,
+    pub help: String,
+}
+
+impl From<&Command> for CommandHelp {
+    fn from(command: &Command) -> Self {
+        Self {
+            name: command.name.to_ascii_uppercase(),
+            aliases: command
+                .aliases
+                .iter()
+                .map(|alias| format!("/{}", alias))
+                .collect(),
+            usage: command.usage(),
+            permission: command.permission,
+            help: command.help.to_string(),
+        }
+    }
+}
+
+/// Every command clients can send.
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "join",
+        aliases: &["j"],
+        args: &[
+            Arg::new("room_name", ArgKind::Required),
+            Arg::new("username", ArgKind::Required),
+        ],
+        permission: Permission::Anyone,
+        help: "Join a room under a username.",
+        protocol: false,
+        parse: parse_join,
+    },
+    Command {
+        name: "say",
+        aliases: &["msg"],
+        args: &[Arg::new("text", ArgKind::Text)],
+        permission: Permission::RoomMember,
+        help: "Send a message to your room, even one starting with a command name.",
+        protocol: false,
+        parse: |args| Ok(UserContract::BroadcastMessage(args.required(0).to_string())),
+    },
+    Command {
+        name: "me",
+        aliases: &["action"],
+        args: &[Arg::new("text", ArgKind::Text)],
+        permission: Permission::RoomMember,
+        help: "Send an action, shown as `* username text`.",
+        protocol: false,
+        parse: |args| Ok(UserContract::Action(args.required(0).to_string())),
+    },
+    Command {
+        name: "notice",
+        aliases: &[],
+        args: &[Arg::new("text", ArgKind::Text)],
+        permission: Permission::RoomMember,
+        help: "Send a notice, which bots never reply to. `NOTICE * {text}` is WALLOPS.",
+        protocol: false,
+        parse: parse_notice,
+    },
+    Command {
+        name: "reply",
+        aliases: &["re"],
+        args: &[
+            Arg::new("message_id", ArgKind::Required),
+            Arg::new("text", ArgKind::Text),
+        ],
+        permission: Permission::RoomMember,
+        help: "Reply to a message of your room.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::Reply {
+                message_id: args.required(0).to_string(),
+                content: args.required(1).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "thread",
+        aliases: &[],
+        args: &[Arg::new("message_id", ArgKind::Required)],
+        permission: Permission::RoomMember,
+        help: "Get a message of your room followed by its replies.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::Thread {
+                message_id: args.required(0).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "edit",
+        aliases: &[],
+        args: &[
+            Arg::new("message_id", ArgKind::Required),
+            Arg::new("text", ArgKind::Text),
+        ],
+        permission: Permission::RoomMember,
+        help: "Edit one of your messages.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::EditMessage {
+                message_id: args.required(0).to_string(),
+                content: args.required(1).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "delete",
+        aliases: &["del"],
+        args: &[Arg::new("message_id", ArgKind::Required)],
+        permission: Permission::RoomMember,
+        help: "Delete one of your messages, or any message of a room you operate.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::DeleteMessage {
+                message_id: args.required(0).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "react",
+        aliases: &[],
+        args: &[
+            Arg::new("message_id", ArgKind::Required),
+            Arg::new("emoji", ArgKind::Required),
+        ],
+        permission: Permission::RoomMember,
+        help: "React to a message with a single emoji.",
+        protocol: false,
+        parse: |args| {
+            let (message_id, emoji) = parse_reaction(args)?;
+            Ok(UserContract::React { message_id, emoji })
+        },
+    },
+    Command {
+        name: "unreact",
+        aliases: &[],
+        args: &[
+            Arg::new("message_id", ArgKind::Required),
+            Arg::new("emoji", ArgKind::Required),
+        ],
+        permission: Permission::RoomMember,
+        help: "Take back a reaction to a message.",
+        protocol: false,
+        parse: |args| {
+            let (message_id, emoji) = parse_reaction(args)?;
+            Ok(UserContract::Unreact { message_id, emoji })
+        },
+    },
+    Command {
+        name: "history",
+        aliases: &["hist"],
+        args: &[Arg::new("count", ArgKind::Optional)],
+        permission: Permission::RoomMember,
+        help: "Get the latest messages of your room (50 by default).",
+        protocol: false,
+        parse: |args| {
+            let count = args
+                .get(0)
+                .map(|count| {
+                    count
+                        .parse()
+                        .map_err(|_| CommandError::InvalidArgument("history count"))
+                })
+                .transpose()?;
+
+            Ok(UserContract::History { count })
+        },
+    },
+    Command {
+        name: "search",
+        aliases: &[],
+        args: &[
+            Arg::new("room_name", ArgKind::Required),
+            Arg::new("query", ArgKind::Text),
+        ],
+        permission: Permission::RoomMember,
+        help: "Search your room's history; the query can filter with from:, after:, before: and page:.",
+        protocol: false,
+        parse: parse_search,
+    },
+    Command {
+        name: "mark_read",
+        aliases: &["read"],
+        args: &[
+            Arg::new("room_name", ArgKind::Required),
+            Arg::new("seq", ArgKind::Required),
+        ],
+        permission: Permission::RoomMember,
+        help: "Mark the messages of your room up to a sequence number as read, for your username.",
+        protocol: false,
+        parse: |args| {
+            let room_name = args.get(0);
+            if !valid_room_name(room_name) {
+                return Err(CommandError::InvalidRoomName);
+            }
+
+            let seq = args
+                .required(1)
+                .parse()
+                .map_err(|_| CommandError::InvalidArgument("sequence number"))?;
+
+            Ok(UserContract::MarkRead {
+                room_name: args.required(0).to_string(),
+                seq,
+            })
+        },
+    },
+    Command {
+        name: "typing",
+        aliases: &[],
+        args: &[Arg::new("start|stop", ArgKind::Required)],
+        permission: Permission::RoomMember,
+        help: "Let your room know you started or stopped typing.",
+        protocol: false,
+        parse: |args| match args.required(0).to_ascii_lowercase().as_str() {
+            "start" => Ok(UserContract::Typing { typing: true }),
+            "stop" => Ok(UserContract::Typing { typing: false }),
+            _ => Err(CommandError::Usage("TYPING {start|stop}".to_string())),
+        },
+    },
+    Command {
+        name: "typing_events",
+        aliases: &[],
+        args: &[Arg::new("on|off", ArgKind::Required)],
+        permission: Permission::Anyone,
+        help: "Opt in or out of typing indicators.",
+        protocol: false,
+        parse: |args| match args.required(0).to_ascii_lowercase().as_str() {
+            "on" => Ok(UserContract::TypingEvents { enabled: true }),
+            "off" => Ok(UserContract::TypingEvents { enabled: false }),
+            _ => Err(CommandError::Usage("TYPING_EVENTS {on|off}".to_string())),
+        },
+    },
+    Command {
+        name: "away",
+        aliases: &[],
+        args: &[Arg::new("reason", ArgKind::OptionalText)],
+        permission: Permission::Anyone,
+        help: "Mark yourself as away, optionally with a reason.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::Away {
+                reason: args.get(0).map(str::to_string),
+            })
+        },
+    },
+    Command {
+        name: "back",
+        aliases: &[],
+        args: &[],
+        permission: Permission::Anyone,
+        help: "Mark yourself as back.",
+        protocol: false,
+        parse: |_| Ok(UserContract::Back),
+    },
+    Command {
+        name: "who",
+        aliases: &["names"],
+        args: &[],
+        permission: Permission::RoomMember,
+        help: "List the members of your room with their presence.",
+        protocol: false,
+        parse: |_| Ok(UserContract::Who),
+    },
+    Command {
+        name: "oper",
+        aliases: &[],
+        args: &[Arg::new("password", ArgKind::Required)],
+        permission: Permission::Anyone,
+        help: "Become a server operator.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::Oper {
+                password: args.required(0).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "wallops",
+        aliases: &[],
+        args: &[Arg::new("text", ArgKind::Text)],
+        permission: Permission::ServerOperator,
+        help: "Send a notice to every connected user.",
+        protocol: false,
+        parse: |args| Ok(UserContract::Wallops(args.required(0).to_string())),
+    },
+    Command {
+        name: "help",
+        aliases: &["?", "commands"],
+        args: &[Arg::new("command", ArgKind::Optional)],
+        permission: Permission::Anyone,
+        help: "List the commands, or explain one of them.",
+        protocol: false,
+        parse: |args| {
+            Ok(UserContract::Help {
+                command: args.get(0).map(str::to_string),
+            })
+        },
+    },
+    Command {
+        name: "resume",
+        aliases: &[],
+        args: &[Arg::new("token", ArgKind::Required)],
+        permission: Permission::Anyone,
+        help: "Take back a dropped session with its resume token.",
+        protocol: true,
+        parse: |args| {
+            Ok(UserContract::Resume {
+                token: args.required(0).to_string(),
+            })
+        },
+    },
+    Command {
+        name: "ping",
+        aliases: &[],
+        args: &[Arg::new("token", ArgKind::Optional)],
+        permission: Permission::Anyone,
+        help: "Check the server is alive; it answers with PONG and the same token.",
+        protocol: true,
+        parse: |args| {
+            Ok(UserContract::Ping {
+                token: args.get(0).unwrap_or_default().to_string(),
+            })
+        },
+    },
+    Command {
+        name: "pong",
+        aliases: &[],
+        args: &[Arg::new("token", ArgKind::Optional)],
+        permission: Permission::Anyone,
+        help: "Answer a heartbeat PING from the server.",
+        protocol: true,
+        parse: |args| {
+            Ok(UserContract::Pong {
+                token: args.get(0).unwrap_or_default().to_string(),
+            })
+        },
+    },
+];
+
+/// Find a command by its name or one of its aliases.
+pub fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.matches(name))
+}
+
+/// Parse a line sent by a client.
+///
+/// Lines starting with `/` are always commands, named in any case or by an
+/// alias, and a line starting with `//` is a message starting with `/`.
+/// Without the prefix, a line starting with a command name in any case
+/// (e.g. `join` or `JOIN`) is a command, but aliases aren't; with
+/// `slash_commands` on, only protocol commands (such as `PONG`) are. Any
+/// other line is a message, unless it starts with a capitalised near miss
+/// of a command, which is answered with the command it was likely meant to
+/// be.
+pub fn parse_line(line: &str, slash_commands: bool) -> Result<UserContract, CommandError> {
+    let line = line.replace("\n", "");
+    if line.trim().is_empty() {
+        return Err(CommandError::Empty);
+    }
+
+    if let Some(message) = line.trim_start().strip_prefix("//") {
+        return Ok(UserContract::BroadcastMessage(format!("/{}", message)));
+    }
+
+    if let Some(invocation) = line.trim_start().strip_prefix('/') {
+        let (name, args) = split_name(invocation);
+        return match find_command(name) {
+            Some(command) => run(command, args),
+            None => Err(unknown_command(name)),
+        };
+    }
+
+    let (name, args) = split_name(&line);
+    match find_command(name) {
+        Some(command)
+            if name.eq_ignore_ascii_case(command.name) && (command.protocol || !slash_commands) =>
+        {
+            run(command, args)
+        }
+        _ if !slash_commands && looks_mistyped(name) => Err(unknown_command(name)),
+        _ => Ok(UserContract::BroadcastMessage(line)),
+    }
+}
+
+/// Split the first word of a line from the rest of it.
+fn split_name(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => line.split_at(end),
+        None => (line, ""),
+    }
+}
+
+/// Split the request tag off a line, e.g. `@label=42 JOIN rust alice`.
+///
+/// A line which doesn't start with a valid tag is left as it is.
+pub fn split_tag(line: &str) -> (Option<&str>, &str) {
+    let tagged = line.trim_start().strip_prefix("@label=").and_then(|rest| {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let tag = &rest[..end];
+        Some((tag, rest[end..].trim_start())).filter(|_| valid_tag(tag))
+    });
+
+    match tagged {
+        Some((tag, line)) => (Some(tag), line),
+        None => (None, line),
+    }
+}
+
+fn run(command: &Command, args: &str) -> Result<UserContract, CommandError> {
+    let args = command.args(args)?;
+    (command.parse)(&args)
+}
+
+/// Error sent for a command which doesn't exist, suggesting the closest one.
+pub fn unknown_command(name: &str) -> CommandError {
+    CommandError::Unknown {
+        name: name.to_string(),
+        suggestion: closest_command(name).map(|command| command.name),
+    }
+}
+
+/// Get whether the first word of a message is a mistyped command, e.g.
+/// `JION`, or an alias sent without the `/` prefix, e.g. `NAMES`: written
+/// in capitals like commands, and nearly a command name.
+fn looks_mistyped(word: &str) -> bool {
+    let capitalised = word.len() > 1 && word.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+
+    capitalised && closest_command(word).is_some()
+}
+
+/// Get the command whose name or alias is closest to a word, if any is close
+/// enough to be a typo: one edit away, or two for names over four letters.
+fn closest_command(word: &str) -> Option<&'static Command> {
+    let word = word.to_ascii_lowercase();
+    COMMANDS
+        .iter()
+        .flat_map(|command| {
+            std::iter::once(command.name)
+                .chain(command.aliases.iter().copied())
+                .map(move |name| (command, name))
+        })
+        .filter(|(_, name)| name.len() > 1)
+        .map(|(command, name)| {
+            let allowed = if name.len() > 4 { 2 } else { 1 };
+            (command, edit_distance(&word, name), allowed)
+        })
+        .filter(|(_, distance, allowed)| distance <= allowed)
+        .min_by_key(|(_, distance, _)| *distance)
+        .map(|(command, _, _)| command)
+}
+
+/// Number of insertions, deletions, substitutions and swaps of adjacent
+/// letters turning one word into another.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+fn parse_join(args: &Args) -> Result<UserContract, CommandError> {
+    let room_name = args.get(0);
+    if !valid_room_name(room_name) {
+        return Err(CommandError::InvalidRoomName);
+    }
+
+    let username = args.get(1);
+    if !valid_username(username) {
+        return Err(CommandError::InvalidUsername);
+    }
+
+    Ok(UserContract::JoinRoom {
+        username: args.required(1).to_string(),
+        room_name: args.required(0).to_string(),
+    })
+}
+
+fn parse_notice(args: &Args) -> Result<UserContract, CommandError> {
+    let content = args.required(0);
+    if content == "*" {
+        return Err(CommandError::EmptyMessage);
+    }
+
+    match content.strip_prefix("* ") {
+        Some(notice) => Ok(UserContract::Wallops(notice.trim_start().to_string())),
+        None => Ok(UserContract::Notice(content.to_string())),
+    }
+}
+
+/// Parse the message id and emoji of `REACT` and `UNREACT`.
+fn parse_reaction(args: &Args) -> Result<(String, String), CommandError> {
+    if !valid_emoji(args.get(1)) {
+        return Err(CommandError::InvalidReaction);
+    }
+
+    Ok((args.required(0).to_string(), args.required(1).to_string()))
+}
+
+fn parse_search(args: &Args) -> Result<UserContract, CommandError> {
+    let room_name = args.get(0);
+    if !valid_room_name(room_name) {
+        return Err(CommandError::InvalidRoomName);
+    }
+
+    let mut query = SearchQuery {
+        text: String::new(),
+        sender: None,
+        after: None,
+        before: None,
+        page: 1,
+    };
+
+    let mut text = Vec::new();
+    for fragment in args.required(1).split_ascii_whitespace() {
+        match fragment.split_once(':') {
+            Some(("from", sender)) => query.sender = Some(sender.to_string()),
+            Some(("after", after)) => query.after = Some(parse_filter(after)?),
+            Some(("before", before)) => query.before = Some(parse_filter(before)?),
+            Some(("page", page)) => query.page = parse_filter(page)?,
+            _ => text.push(fragment),
+        }
+    }
+
+    if text.is_empty() || query.page == 0 {
+        return Err(CommandError::InvalidSearch);
+    }
+
+    query.text = text.join(" ");
+    Ok(UserContract::Search {
+        room_name: args.required(0).to_string(),
+        query,
+    })
+}
+
+/// Parse the value of a search filter.
+fn parse_filter<T: FromStr>(value: &str) -> Result<T, CommandError> {
+    value
+        .parse()
+        .map_err(|_| CommandError::InvalidSearchFilter(value.to_string()))
+}
+
+/// Get the text following the first `count` whitespace separated fragments.
+fn remainder(line: &str, count: usize) -> Option<&str> {
+    let mut rest = line.trim();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        rest = rest[end..].trim_start();
+    }
+
+    Some(rest).filter(|rest| !rest.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<UserContract, CommandError> {
+        parse_line(line, false)
+    }
+
+    fn is_message(result: Result<UserContract, CommandError>, expected: &str) -> bool {
+        matches!(result, Ok(UserContract::BroadcastMessage(content)) if content == expected)
+    }
+
+    #[test]
+    fn command_names_are_commands_in_any_case() {
+        assert!(matches!(
+            parse("JOIN rust alice"),
+            Ok(UserContract::JoinRoom { room_name, username }) if room_name == "rust" && username == "alice"
+        ));
+        assert!(matches!(
+            parse("join rust alice"),
+            Ok(UserContract::JoinRoom { room_name, username }) if room_name == "rust" && username == "alice"
+        ));
+        assert!(matches!(parse("Who"), Ok(UserContract::Who)));
+        assert!(matches!(parse("ME waves"), Ok(UserContract::Action(action)) if action == "waves"));
+    }
+
+    #[test]
+    fn chat_lines_not_starting_with_a_command_name_are_messages() {
+        assert!(is_message(parse("re: the deploy"), "re: the deploy"));
+        assert!(is_message(parse("names please"), "names please"));
+        assert!(is_message(parse("jion us"), "jion us"));
+        assert!(is_message(parse("hello there"), "hello there"));
+    }
+
+    #[test]
+    fn slash_prefix_accepts_any_case_and_aliases() {
+        assert!(matches!(
+            parse("/join rust alice"),
+            Ok(UserContract::JoinRoom { .. })
+        ));
+        assert!(matches!(parse("/names"), Ok(UserContract::Who)));
+        assert!(matches!(
+            parse("/re abc hello"),
+            Ok(UserContract::Reply { message_id, content }) if message_id == "abc" && content == "hello"
+        ));
+        assert!(matches!(
+            parse("/ join rust alice"),
+            Ok(UserContract::JoinRoom { room_name, username }) if room_name == "rust" && username == "alice"
+        ));
+    }
+
+    #[test]
+    fn double_slash_escapes_a_message() {
+        assert!(is_message(parse("//shrug"), "/shrug"));
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(
+            parse("/jion rust alice").unwrap_err(),
+            CommandError::Unknown {
+                name: "jion".to_string(),
+                suggestion: Some("join"),
+            }
+        );
+        assert_eq!(
+            parse("JION rust alice").unwrap_err().code(),
+            ErrorCode::UnknownCommand
+        );
+        assert_eq!(
+            parse("NAMES").unwrap_err(),
+            CommandError::Unknown {
+                name: "NAMES".to_string(),
+                suggestion: Some("who"),
+            }
+        );
+        assert_eq!(
+            parse("/frobnicate").unwrap_err().to_string(),
+            "Unknown command [frobnicate], send HELP for a list"
+        );
+    }
+
+    #[test]
+    fn slash_commands_mode_only_recognises_protocol_commands_bare() {
+        assert!(is_message(
+            parse_line("JOIN rust alice", true),
+            "JOIN rust alice"
+        ));
+        assert!(is_message(
+            parse_line("JION rust alice", true),
+            "JION rust alice"
+        ));
+        assert!(matches!(
+            parse_line("PONG 42", true),
+            Ok(UserContract::Pong { token }) if token == "42"
+        ));
+        assert!(matches!(parse_line("/who", true), Ok(UserContract::Who)));
+    }
+
+    #[test]
+    fn empty_lines_are_errors() {
+        assert_eq!(parse("   ").unwrap_err(), CommandError::Empty);
+    }
+
+    #[test]
+    fn arguments_are_checked_against_the_schema() {
+        assert_eq!(
+            parse("JOIN rust").unwrap_err(),
+            CommandError::Usage("JOIN {room_name} {username}".to_string())
+        );
+        assert_eq!(
+            parse("WHO cares").unwrap_err(),
+            CommandError::Usage("WHO".to_string())
+        );
+        assert!(matches!(
+            parse("HISTORY"),
+            Ok(UserContract::History { count: None })
+        ));
+        assert_eq!(
+            parse("HISTORY lots").unwrap_err(),
+            CommandError::InvalidArgument("history count")
+        );
+        assert!(matches!(
+            parse("AWAY out for lunch"),
+            Ok(UserContract::Away { reason: Some(reason) }) if reason == "out for lunch"
+        ));
+    }
+
+    #[test]
+    fn parse_errors_map_to_error_codes() {
+        assert_eq!(
+            parse("JOIN abcdefghijklmnopqrstu alice")
+                .unwrap_err()
+                .code(),
+            ErrorCode::InvalidRoomName
+        );
+        assert_eq!(
+            parse("REACT abc x").unwrap_err().code(),
+            ErrorCode::InvalidReaction
+        );
+        assert_eq!(
+            parse("SEARCH rust page:0").unwrap_err().code(),
+            ErrorCode::InvalidSearch
+        );
+        assert_eq!(
+            parse("SEARCH rust deploy after:yesterday").unwrap_err(),
+            CommandError::InvalidSearchFilter("yesterday".to_string())
+        );
+    }
+
+    #[test]
+    fn search_filters_are_parsed() {
+        let query = match parse("SEARCH rust deploy failed from:alice after:10 page:2") {
+            Ok(UserContract::Search { query, .. }) => query,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(query.text, "deploy failed");
+        assert_eq!(query.sender.as_deref(), Some("alice"));
+        assert_eq!(query.after, Some(10));
+        assert_eq!(query.before, None);
+        assert_eq!(query.page, 2);
+    }
+
+    #[test]
+    fn edit_distance_counts_adjacent_swaps_as_one_edit() {
+        assert_eq!(edit_distance("join", "join"), 0);
+        assert_eq!(edit_distance("jion", "join"), 1);
+        assert_eq!(edit_distance("jon", "join"), 1);
+        assert_eq!(edit_distance("histroy", "history"), 1);
+        assert_eq!(edit_distance("", "who"), 3);
+    }
+
+    #[test]
+    fn closest_command_only_matches_typos() {
+        assert_eq!(closest_command("JION").map(|c| c.name), Some("join"));
+        assert_eq!(closest_command("serach").map(|c| c.name), Some("search"));
+        assert!(closest_command("hello").is_none());
+        assert!(closest_command("OK").is_none());
+    }
+
+    #[test]
+    fn commands_are_found_by_name_or_alias_in_any_case() {
+        assert_eq!(find_command("Reply").map(|c| c.name), Some("reply"));
+        assert_eq!(find_command("RE").map(|c| c.name), Some("reply"));
+        assert_eq!(find_command("?").map(|c| c.name), Some("help"));
+        assert!(find_command("frobnicate").is_none());
+    }
+
+    #[test]
+    fn every_command_name_and_alias_is_unique() {
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .flat_map(|command| {
+                std::iter::once(command.name).chain(command.aliases.iter().copied())
+            })
+            .collect();
+        let count = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+
+    #[test]
+    fn usage_lists_the_arguments() {
+        let reply = find_command("reply").unwrap();
+        assert_eq!(reply.usage(), "REPLY {message_id} {text}");
+        assert_eq!(find_command("history").unwrap().usage(), "HISTORY [count]");
+    }
+
+    #[test]
+    fn split_tag_takes_a_leading_label() {
+        assert_eq!(
+            split_tag("@label=42 JOIN rust alice"),
+            (Some("42"), "JOIN rust alice")
+        );
+        assert_eq!(
+            split_tag("  @label=a.b-c_1   WHO"),
+            (Some("a.b-c_1"), "WHO")
+        );
+        assert_eq!(split_tag("@label=42"), (Some("42"), ""));
+    }
+
+    #[test]
+    fn split_tag_leaves_lines_without_a_valid_label() {
+        assert_eq!(split_tag("JOIN rust alice"), (None, "JOIN rust alice"));
+        assert_eq!(split_tag("@label= WHO"), (None, "@label= WHO"));
+        assert_eq!(split_tag("@label=4/2 WHO"), (None, "@label=4/2 WHO"));
+        assert_eq!(split_tag("hi @label=42"), (None, "hi @label=42"));
+
+        let long = format!("@label={} WHO", "a".repeat(65));
+        assert_eq!(split_tag(&long), (None, long.as_str()));
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+/// Wire format of the frames sent to a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Plain text lines terminated by `<NL>`.
+    Text,
+
+    /// One JSON object per line.
+    Json,
+}
+
+/// Settings applied to every session accepted by a listener.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// How often heartbeat pings are sent.
+    pub heartbeat_interval: Duration,
+
+    /// How long before lack of client response causes a timeout.
+    pub client_timeout: Duration,
+
+    /// How long without activity (other than heartbeats) before the user is idle.
+    pub idle_timeout: Duration,
+
+    /// Wire format of the frames sent to clients.
+    pub protocol: Protocol,
+
+    /// Prefix text-protocol chat messages with their sequence number, id and timestamp.
+    pub message_prefix: bool,
+
+    /// Only recognise commands prefixed with `/`, sending any other line as a message.
+    pub slash_commands: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(60),
+            client_timeout: Duration::from_secs(300),
+            idle_timeout: Duration::from_secs(600),
+            protocol: Protocol::Text,
+            message_prefix: false,
+            slash_commands: false,
+        }
+    }
+}
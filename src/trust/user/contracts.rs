@@ -1,45 +1,82 @@
-use crate::trust::server::utils::{valid_room_name, valid_username};
+use super::commands::{parse_line, CommandError};
+use crate::trust::room::SearchQuery;
 use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum UserContract {
-    JoinRoom { username: String, room_name: String },
+    JoinRoom {
+        username: String,
+        room_name: String,
+    },
     BroadcastMessage(String),
+    Action(String),
+    Notice(String),
+    Resume {
+        token: String,
+    },
+    Ping {
+        token: String,
+    },
+    Pong {
+        token: String,
+    },
+    EditMessage {
+        message_id: String,
+        content: String,
+    },
+    DeleteMessage {
+        message_id: String,
+    },
+    Reply {
+        message_id: String,
+        content: String,
+    },
+    Thread {
+        message_id: String,
+    },
+    React {
+        message_id: String,
+        emoji: String,
+    },
+    Unreact {
+        message_id: String,
+        emoji: String,
+    },
+    History {
+        count: Option<usize>,
+    },
+    Typing {
+        typing: bool,
+    },
+    TypingEvents {
+        enabled: bool,
+    },
+    MarkRead {
+        room_name: String,
+        seq: u64,
+    },
+    Away {
+        reason: Option<String>,
+    },
+    Back,
+    Who,
+    Oper {
+        password: String,
+    },
+    Wallops(String),
+    Search {
+        room_name: String,
+        query: SearchQuery,
+    },
+    Help {
+        command: Option<String>,
+    },
 }
 
 impl FromStr for UserContract {
-    type Err = String;
+    type Err = CommandError;
 
     fn from_str(message: &str) -> Result<Self, Self::Err> {
-        let message = message.replace("\n", "");
-        let mut fragments = message.split_ascii_whitespace();
-        let command = fragments
-            .next()
-            .ok_or("Command cannot be empty".to_string())?;
-
-        match command.to_ascii_lowercase().as_str() {
-            "join" => {
-                let room_name = fragments.next();
-                if !valid_room_name(room_name) {
-                    return Err("Invalid room name".to_string());
-                }
-
-                let username = fragments.next();
-                if !valid_username(username) {
-                    return Err("Invalid username".to_string());
-                }
-
-                if fragments.next().is_some() {
-                    return Err("Invalid join command specified".to_string());
-                }
-
-                Ok(Self::JoinRoom {
-                    username: username.unwrap().to_string(),
-                    room_name: room_name.unwrap().to_string(),
-                })
-            }
-
-            _ => Ok(Self::BroadcastMessage(message)),
-        }
+        parse_line(message, false)
     }
 }
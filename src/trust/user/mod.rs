@@ -1,15 +1,23 @@
+mod commands;
+mod config;
 mod contracts;
 
 use self::contracts::UserContract;
+pub use self::{commands::*, config::*};
 use super::{
     codec::TrustTcpCodec,
-    response::error_message,
+    errors::{ClientError, ErrorCode},
+    metrics,
+    response::{ping_message, pong_message, render_message, resume_token_message},
+    room::MessageKind,
     server::{
-        contracts::{RoomContract, ConnectContract, DisconnectContract, PlainTextMessage},
-        TrustServer,
+        contracts::{
+            ConnectContract, DisconnectContract, OperatorContract, PlainTextMessage,
+            PresenceContract, ResumeContract, RoomContract, TypingContract,
+        },
+        TrustServer, TrustServerError,
     },
 };
-use crate::log;
 use actix::{
     clock::Instant,
     fut,
@@ -19,93 +27,329 @@ use actix::{
 };
 use std::{
     io::{self, ErrorKind},
+    net::SocketAddr,
     time::Duration,
 };
 use tokio::io::WriteHalf;
 use tokio::net::TcpStream;
+use tracing::{debug, field, info, info_span, warn, Span};
 
 pub struct User {
     id: Option<String>,
+    peer_addr: SocketAddr,
+    span: Span,
+    config: SessionConfig,
     last_heartbeat_time: Instant,
+    last_activity_time: Instant,
+    idle: bool,
+    ping_sequence: u64,
+    pending_ping: Option<(String, Instant)>,
+    round_trip_time: Option<Duration>,
+    typing_events: bool,
     chat_server: Addr<TrustServer>,
     framed: FramedWrite<String, WriteHalf<TcpStream>, TrustTcpCodec>,
 }
 
 impl User {
-    /// How often heartbeat pings are sent
-    const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
-
-    /// How long before lack of client response causes a timeout
-    const CLIENT_TIMEOUT: Duration = Duration::from_secs(300);
-
     // Create a new instance of user.
     pub fn new(
         chat_server_address: Addr<TrustServer>,
         framed: FramedWrite<String, WriteHalf<TcpStream>, TrustTcpCodec>,
+        peer_addr: SocketAddr,
+        config: SessionConfig,
     ) -> Self {
+        // Typing indicators are only sent to text clients that opt in.
+        let typing_events = config.protocol == Protocol::Json;
+
         Self {
             id: None,
+            peer_addr,
+            span: info_span!("session", session_id = field::Empty, peer_addr = %peer_addr),
+            config,
             last_heartbeat_time: Instant::now(),
+            last_activity_time: Instant::now(),
+            idle: false,
+            ping_sequence: 0,
+            pending_ping: None,
+            round_trip_time: None,
+            typing_events,
             chat_server: chat_server_address,
             framed,
         }
     }
 
-    /// Start process to check ping user at interval.
+    /// Start process to ping user at interval, stopping the session once
+    /// the client hasn't been heard from within the timeout.
     fn heartbeat(&self, ctx: &mut Context<Self>) {
-        ctx.run_interval(User::HEARTBEAT_INTERVAL, |user, ctx| {
+        ctx.run_interval(self.config.heartbeat_interval, |user, ctx| {
             let time_diff = Instant::now().duration_since(user.last_heartbeat_time);
-            if time_diff <= User::CLIENT_TIMEOUT {
-                return user.framed.write("".to_string());
-            }
-
-            if let Some(user_id) = &user.id {
-                log!("Disconnecting user [{}] after heartbeat failed!", user_id);
-
-                user.chat_server.do_send(DisconnectContract {
-                    user_id: user_id.to_string(),
-                });
+            if time_diff <= user.config.client_timeout {
+                user.check_idle();
+                return user.send_ping();
             }
 
+            metrics::HEARTBEAT_TIMEOUTS.inc();
+            let _span = user.span.enter();
+            info!("Disconnecting user after heartbeat failed");
             ctx.stop();
-            return;
         });
     }
 
+    /// Write a message to the client in the session's protocol.
+    fn write(&mut self, message: impl Into<PlainTextMessage>) {
+        metrics::MESSAGES_SENT.inc();
+        self.framed.write(render_message(
+            &message.into(),
+            self.config.protocol,
+            self.config.message_prefix,
+        ));
+    }
+
+    /// Send a heartbeat ping the client is expected to answer with a pong.
+    fn send_ping(&mut self) {
+        self.ping_sequence += 1;
+        let token = self.ping_sequence.to_string();
+
+        self.write(ping_message(&token));
+        self.pending_ping = Some((token, Instant::now()));
+    }
+
+    /// Mark the user idle once the client has only answered heartbeats
+    /// within the idle timeout.
+    ///
+    /// `last_heartbeat_time` is refreshed by every frame, pongs included, so
+    /// activity is tracked separately in `last_activity_time`.
+    fn check_idle(&mut self) {
+        let idle = self.last_activity_time.elapsed() >= self.config.idle_timeout;
+        if idle && !self.idle {
+            self.set_idle(true);
+        }
+    }
+
+    /// Record activity from the client, other than heartbeats.
+    fn record_activity(&mut self) {
+        self.last_activity_time = Instant::now();
+        if self.idle {
+            self.set_idle(false);
+        }
+    }
+
+    /// Let the chat server know whether the user is idle.
+    fn set_idle(&mut self, idle: bool) {
+        let user_id = match &self.id {
+            Some(user_id) => user_id.clone(),
+            None => return,
+        };
+
+        self.idle = idle;
+        match idle {
+            true => self.chat_server.do_send(PresenceContract::Idle { user_id }),
+            false => self
+                .chat_server
+                .do_send(PresenceContract::Active { user_id }),
+        }
+    }
+
+    /// Record the round-trip time of a pong answering our last ping.
+    fn handle_pong(&mut self, token: &str) {
+        match self.pending_ping.take() {
+            Some((expected, sent_at)) if expected == token => {
+                let round_trip_time = sent_at.elapsed();
+                metrics::HEARTBEAT_ROUND_TRIP.observe(round_trip_time.as_secs_f64());
+                self.round_trip_time = Some(round_trip_time);
+
+                let _span = self.span.enter();
+                debug!(round_trip_time = ?self.round_trip_time, "Heartbeat acknowledged");
+            }
+            pending => self.pending_ping = pending,
+        }
+    }
+
     // Attempt to register client session to the chat server.
     fn connect_to_chat_server(&self, ctx: &mut Context<Self>) {
         let connect_req = ConnectContract {
             addr: ctx.address().recipient(),
+            peer_addr: self.peer_addr,
         };
 
         self.chat_server
             .send(connect_req)
             .into_actor(self)
             .then(|response, user, ctx| {
-                if let Ok(Ok(id)) = response {
+                if let Ok(Ok((id, resume_token))) = response {
+                    user.span.record("session_id", id.as_str());
                     user.id.replace(id);
+                    user.write(resume_token_message(&resume_token));
                     return fut::ready(());
                 }
 
+                let _span = user.span.enter();
+                warn!("Failed to register session with the chat server");
                 ctx.stop();
                 fut::ready(())
             })
             .wait(ctx);
     }
 
+    // Attempt to take over a dropped session using its resume token.
+    fn resume_session(&mut self, token: String, tag: Option<String>, ctx: &mut Context<Self>) {
+        let user_id = match &self.id {
+            Some(user_id) => user_id.clone(),
+            None => return self.reply(tag, Err(not_connected())),
+        };
+
+        let resume_req = ResumeContract {
+            user_id,
+            token,
+            addr: ctx.address().recipient(),
+        };
+
+        self.chat_server
+            .send(resume_req)
+            .into_actor(self)
+            .then(|response, user, _| {
+                let error = match response {
+                    Ok(Ok(id)) => {
+                        user.span.record("session_id", id.as_str());
+                        user.id.replace(id);
+                        user.reply(tag, Ok(()));
+                        return fut::ready(());
+                    }
+                    Ok(Err(err)) => ClientError::from(err),
+                    Err(err) => ClientError::new(ErrorCode::Internal, err.to_string()),
+                };
+
+                user.span
+                    .in_scope(|| warn!(error = %error.message, "Failed to resume session"));
+                user.reply(tag, Err(error));
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
     /// Handle a message received from a client.
-    fn handle_message(&mut self, message: String, _: &mut Context<Self>) {
-        if let Ok(cmd) = message.parse::<UserContract>() {
-            if let Some(cmd) = self.map_to_server_command(cmd, &message) {
-                return self.chat_server.do_send(cmd);
+    ///
+    /// Commands may be tagged, e.g. `@label=42 JOIN rust alice`, to be
+    /// answered with `OK 42` or `ERR 42 {code}` once handled.
+    fn handle_message(&mut self, message: String, ctx: &mut Context<Self>) {
+        metrics::MESSAGES_RECEIVED.inc();
+
+        let (tag, line) = split_tag(&message);
+        let tag = tag.map(str::to_string);
+        let command = parse_line(line, self.config.slash_commands);
+        if !matches!(
+            command,
+            Ok(UserContract::Ping { .. }) | Ok(UserContract::Pong { .. })
+        ) {
+            self.record_activity();
+        }
+
+        let result = match command {
+            Ok(UserContract::Resume { token }) => return self.resume_session(token, tag, ctx),
+            Ok(UserContract::Ping { token }) => {
+                self.write(pong_message(&token));
+                Ok(())
+            }
+            Ok(UserContract::Pong { token }) => {
+                self.handle_pong(&token);
+                Ok(())
             }
+            Ok(UserContract::TypingEvents { enabled }) => {
+                self.typing_events = enabled;
+                Ok(())
+            }
+            Ok(UserContract::Help { command }) => self.send_help(command.as_deref()),
+            Ok(UserContract::Away { reason }) => {
+                if let Some(user_id) = self.id.clone() {
+                    return self.request(PresenceContract::Away { user_id, reason }, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Ok(UserContract::Oper { password }) => {
+                if let Some(user_id) = self.id.clone() {
+                    return self.request(OperatorContract::Oper { user_id, password }, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Ok(UserContract::Wallops(text)) => {
+                if let Some(user_id) = self.id.clone() {
+                    return self.request(OperatorContract::Wallops { user_id, text }, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Ok(UserContract::Back) => {
+                if let Some(user_id) = self.id.clone() {
+                    return self.request(PresenceContract::Back { user_id }, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Ok(UserContract::Typing { typing }) => {
+                if let Some(user_id) = self.id.clone() {
+                    return self.request(TypingContract { user_id, typing }, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Ok(cmd) => {
+                if let Some(cmd) = self.map_to_server_command(cmd) {
+                    return self.request(cmd, tag, ctx);
+                }
+                Err(not_connected())
+            }
+            Err(err) => Err(err.into()),
+        };
+
+        self.reply(tag, result);
+    }
+
+    /// Send a command to the chat server, and let the client know how it
+    /// went once the server handled it.
+    fn request<M>(&mut self, msg: M, tag: Option<String>, ctx: &mut Context<Self>)
+    where
+        M: actix::Message<Result = Result<(), TrustServerError>> + Send + 'static,
+        TrustServer: Handler<M>,
+    {
+        self.chat_server
+            .send(msg)
+            .into_actor(self)
+            .then(|response, user, _| {
+                let result = match response {
+                    Ok(result) => result.map_err(ClientError::from),
+                    Err(err) => Err(ClientError::new(ErrorCode::Internal, err.to_string())),
+                };
+
+                user.reply(tag, result);
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
+    /// Let the client know how a command went: tagged commands are always
+    /// answered, untagged ones only when they failed.
+    fn reply(&mut self, tag: Option<String>, result: Result<(), ClientError>) {
+        match (tag, result) {
+            (Some(tag), Ok(())) => self.write(PlainTextMessage::Ok { tag }),
+            (Some(tag), Err(error)) => self.write(PlainTextMessage::Err { tag, error }),
+            (None, Ok(())) => {}
+            (None, Err(error)) => self.write(error),
         }
+    }
+
+    /// Send help about every command, or about the one asked for.
+    fn send_help(&mut self, name: Option<&str>) -> Result<(), ClientError> {
+        let commands = match name {
+            Some(name) => match find_command(name.trim_start_matches('/')) {
+                Some(command) => vec![CommandHelp::from(command)],
+                None => return Err(unknown_command(name).into()),
+            },
+            None => COMMANDS.iter().map(CommandHelp::from).collect(),
+        };
 
-        return self.framed.write(error_message());
+        self.write(PlainTextMessage::Help { commands });
+        Ok(())
     }
 
     /// Map a chat session command to a chat server command
-    fn map_to_server_command(&self, cmd: UserContract, message: &str) -> Option<RoomContract> {
+    fn map_to_server_command(&self, cmd: UserContract) -> Option<RoomContract> {
         let cmd = match cmd {
             UserContract::JoinRoom {
                 room_name,
@@ -114,19 +358,105 @@ impl User {
                 user_id: self.id.clone()?,
                 room_name: room_name.to_string(),
                 username: username.to_string(),
-                raw: message.to_string(),
             },
 
             UserContract::BroadcastMessage(content) => RoomContract::BroadcastMessage {
                 user_id: self.id.clone()?,
                 content,
+                kind: MessageKind::Normal,
+            },
+
+            UserContract::Action(content) => RoomContract::BroadcastMessage {
+                user_id: self.id.clone()?,
+                content,
+                kind: MessageKind::Action,
+            },
+
+            UserContract::Notice(content) => RoomContract::BroadcastMessage {
+                user_id: self.id.clone()?,
+                content,
+                kind: MessageKind::Notice,
+            },
+
+            UserContract::EditMessage {
+                message_id,
+                content,
+            } => RoomContract::EditMessage {
+                user_id: self.id.clone()?,
+                message_id,
+                content,
+            },
+
+            UserContract::DeleteMessage { message_id } => RoomContract::DeleteMessage {
+                user_id: self.id.clone()?,
+                message_id,
+            },
+
+            UserContract::Reply {
+                message_id,
+                content,
+            } => RoomContract::Reply {
+                user_id: self.id.clone()?,
+                message_id,
+                content,
+            },
+
+            UserContract::Thread { message_id } => RoomContract::Thread {
+                user_id: self.id.clone()?,
+                message_id,
+            },
+
+            UserContract::React { message_id, emoji } => RoomContract::React {
+                user_id: self.id.clone()?,
+                message_id,
+                emoji,
+                add: true,
+            },
+
+            UserContract::Unreact { message_id, emoji } => RoomContract::React {
+                user_id: self.id.clone()?,
+                message_id,
+                emoji,
+                add: false,
             },
+
+            UserContract::History { count } => RoomContract::History {
+                user_id: self.id.clone()?,
+                count,
+            },
+
+            UserContract::MarkRead { room_name, seq } => RoomContract::MarkRead {
+                user_id: self.id.clone()?,
+                room_name,
+                seq,
+            },
+
+            UserContract::Search { room_name, query } => RoomContract::Search {
+                user_id: self.id.clone()?,
+                room_name,
+                query,
+            },
+
+            UserContract::Who => RoomContract::Who {
+                user_id: self.id.clone()?,
+            },
+
+            UserContract::Resume { .. }
+            | UserContract::Away { .. }
+            | UserContract::Back
+            | UserContract::Oper { .. }
+            | UserContract::Wallops(_)
+            | UserContract::Ping { .. }
+            | UserContract::Pong { .. }
+            | UserContract::Typing { .. }
+            | UserContract::TypingEvents { .. }
+            | UserContract::Help { .. } => return None,
         };
 
         Some(cmd)
     }
 
-    /// Disconnect a c
+    /// Disconnect the user from the chat server.
     fn disconnect(&self) {
         if let Some(ref user_id) = self.id {
             let disconnect_msg = DisconnectContract {
@@ -138,6 +468,14 @@ impl User {
     }
 }
 
+/// Error for a command sent before the session registered with the server.
+fn not_connected() -> ClientError {
+    ClientError::new(
+        ErrorCode::NotConnected,
+        "session is not registered with the server",
+    )
+}
+
 impl Actor for User {
     type Context = Context<Self>;
 
@@ -160,7 +498,9 @@ impl StreamHandler<Result<String, io::Error>> for User {
         match msg {
             Ok(text) => self.handle_message(text, ctx),
             Err(err) => {
-                log!("Error occurred {:?}", err.kind());
+                metrics::CODEC_ERRORS.inc();
+                let _span = self.span.enter();
+                warn!(error = %err, "Failed to read from client");
                 if err.kind() == ErrorKind::Other {
                     ctx.stop();
                 }
@@ -169,13 +509,27 @@ impl StreamHandler<Result<String, io::Error>> for User {
     }
 }
 
-/// Handle messages from chat server; we simply send it to peer websocket
+/// Handle messages from chat server; we simply send it to peer websocket,
+/// unless it is a typing indicator the client hasn't opted in to.
 impl Handler<PlainTextMessage> for User {
     type Result = ();
 
     fn handle(&mut self, msg: PlainTextMessage, _: &mut Self::Context) {
-        self.framed.write(msg.0 + "\n");
+        if let PlainTextMessage::Typing { .. } = msg {
+            if !self.typing_events {
+                return;
+            }
+        }
+
+        self.write(msg);
     }
 }
 
-impl WriteHandler<io::Error> for User {}
+impl WriteHandler<io::Error> for User {
+    fn error(&mut self, err: io::Error, _: &mut Self::Context) -> Running {
+        metrics::CODEC_ERRORS.inc();
+        let _span = self.span.enter();
+        warn!(error = %err, "Failed to write to client");
+        Running::Stop
+    }
+}